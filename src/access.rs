@@ -0,0 +1,52 @@
+//! Access-pattern hints for ranges of a mapped file.
+
+use libc::{
+    MADV_COLD, MADV_DONTNEED, MADV_NORMAL, MADV_PAGEOUT, MADV_RANDOM, MADV_SEQUENTIAL,
+    MADV_WILLNEED,
+};
+
+/// How a range of elements is about to be used, passed to `advise`.
+///
+/// Each variant maps to one `madvise` advice. Hints never change the data:
+/// the mappings are shared, so pages dropped by [`DontNeed`](Self::DontNeed)
+/// or [`PageOut`](Self::PageOut) are read back from the file on next access.
+///
+/// Hints cover whole pages, so elements sharing a page with either end of
+/// the range are affected too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// No particular pattern; undoes [`Sequential`](Self::Sequential) and
+    /// [`Random`](Self::Random).
+    #[default]
+    Normal,
+    /// The range will be scanned in order, so read ahead aggressively and
+    /// drop pages soon after they are read.
+    Sequential,
+    /// The range will be read in no particular order, so do not read ahead.
+    Random,
+    /// The range will be read soon; start reading it in now.
+    WillNeed,
+    /// The range will not be read soon; drop it from this process's page
+    /// tables right away.
+    DontNeed,
+    /// The range is cold; reclaim it first under memory pressure.
+    /// Needs Linux 5.4.
+    Cold,
+    /// Write back and reclaim the range now. Needs Linux 5.4.
+    PageOut,
+}
+
+impl Access {
+    /// The `madvise` advice for this hint.
+    pub(crate) fn advice(self) -> i32 {
+        match self {
+            Self::Normal => MADV_NORMAL,
+            Self::Sequential => MADV_SEQUENTIAL,
+            Self::Random => MADV_RANDOM,
+            Self::WillNeed => MADV_WILLNEED,
+            Self::DontNeed => MADV_DONTNEED,
+            Self::Cold => MADV_COLD,
+            Self::PageOut => MADV_PAGEOUT,
+        }
+    }
+}
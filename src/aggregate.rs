@@ -0,0 +1,116 @@
+//! Aggregation of series points over fixed time windows.
+
+use crate::series::Points;
+
+/// How the values in a window are combined into one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    Min,
+    Max,
+    Sum,
+    Count,
+    Mean,
+    /// The value with the earliest timestamp.
+    First,
+    /// The value with the latest timestamp.
+    Last,
+    /// Population standard deviation.
+    StdDev,
+}
+
+impl Aggregation {
+    /// Combines `values`, which must not be empty.
+    pub fn apply(self, values: &[f64]) -> f64 {
+        debug_assert!(!values.is_empty());
+        match self {
+            Self::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Self::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Self::Sum => values.iter().sum(),
+            Self::Count => values.len() as f64,
+            Self::Mean => mean(values),
+            Self::First => values[0],
+            Self::Last => values[values.len() - 1],
+            Self::StdDev => {
+                // two passes over the window are cheaper than they sound, as
+                // it is in cache after the first, and avoid the cancellation
+                // of the sum-of-squares formula
+                let mean = mean(values);
+                let variance =
+                    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+                variance.sqrt()
+            }
+        }
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// An aggregated series, held in memory.
+///
+/// Each point stands for one window: its timestamp is the start of the window
+/// and its value the aggregate of the points in it. Windows without points
+/// are left out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Aggregated {
+    pub timestamps: Vec<i64>,
+    pub values: Vec<f64>,
+}
+
+impl Aggregated {
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// Borrows the points, to query them like those of a [`Series`].
+    ///
+    /// [`Series`]: crate::Series
+    pub fn points(&self) -> Points<'_> {
+        Points {
+            timestamps: &self.timestamps,
+            values: &self.values,
+        }
+    }
+}
+
+/// Aggregates `points` over windows `width` timestamp units wide.
+///
+/// Windows are aligned to multiples of `width`, so with millisecond
+/// timestamps and a `width` of 60 000 they are the minutes of the clock. A
+/// window reaching below `i64::MIN` is reported as starting there.
+/// `points` must be in timestamp order, as those of a series are; each
+/// window is found by binary search and aggregated straight from the slices,
+/// which for a [`Series`] means from the mapped columns.
+///
+/// # Panics
+///
+/// If `width` is not positive.
+///
+/// [`Series`]: crate::Series
+pub fn aggregate(points: Points<'_>, width: i64, aggregation: Aggregation) -> Aggregated {
+    assert!(width > 0, "window width must be positive, got {width}");
+
+    let mut out = Aggregated::default();
+    let mut timestamps = points.timestamps;
+    let mut values = points.values;
+    while let Some(&ts) = timestamps.first() {
+        let offset = ts.rem_euclid(width);
+        let start = ts.checked_sub(offset).unwrap_or(i64::MIN);
+        let len = match ts.checked_add(width - offset) {
+            Some(end) => timestamps.partition_point(|&ts| ts < end),
+            // the last window runs past i64::MAX
+            None => timestamps.len(),
+        };
+
+        out.timestamps.push(start);
+        out.values.push(aggregation.apply(&values[..len]));
+        timestamps = &timestamps[len..];
+        values = &values[len..];
+    }
+    out
+}
@@ -0,0 +1,610 @@
+//! Appending to a vector file with `pwrite` or io_uring instead of a
+//! mapping.
+
+use std::{
+    alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout},
+    fs::File,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    ptr::NonNull,
+    sync::atomic::Ordering::Relaxed,
+};
+
+use libc::{fallocate, fcntl, F_GETFL, F_SETFL, O_DIRECT};
+
+use crate::{
+    error::{FmvError, Result},
+    growth::GrowthPolicy,
+    header::{FMVHeader, FLAG_DIRTY, HEADER_SIZE},
+    lock::{self, LockMode},
+    pod::{self, Pod},
+    storage::Storage,
+    uring::Ring,
+};
+
+/// Alignment of `O_DIRECT` buffers, file offsets and write lengths. A page
+/// satisfies the logical block size of any common device.
+const DIRECT_ALIGN: usize = 4096;
+
+/// Default size of the append buffer.
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// A vector of `T` that is only appended to, written with `pwrite` from an
+/// in-memory buffer.
+///
+/// Appends are copied into the buffer and written out whenever it fills, so
+/// the kernel sees a few large writes instead of page faults on a mapping,
+/// and the data reaching the disk is exactly what was written. With
+/// [`BufferedOptions::direct`] the writes bypass the page cache entirely, and
+/// with [`BufferedOptions::io_uring`] they are submitted without blocking.
+///
+/// The file format is the same as [`FileMappedVector`]'s, including the
+/// durability model: the header length only advances in
+/// [`flush`](Self::flush), after the data is synced, and a file left dirty by
+/// a crash is rolled back to it on open. [`FileMappedSlice`] and
+/// [`FileMappedReader`] read these files too, seeing elements once they are
+/// flushed.
+///
+/// Once a write or sync has failed in a way that may have lost appended
+/// data, every further call fails with [`FmvError::Poisoned`], and dropping
+/// the vector leaves the file to be rolled back to the last flush when it is
+/// reopened.
+///
+/// [`FileMappedVector`]: crate::FileMappedVector
+/// [`FileMappedSlice`]: crate::FileMappedSlice
+/// [`FileMappedReader`]: crate::FileMappedReader
+pub struct BufferedVector<T: Pod> {
+    // declared before `header`, which a sync in flight may still be writing
+    engine: Engine,
+    file: File,
+    // the header page, always written whole so it works with O_DIRECT
+    header: AlignedBuf,
+    // the buffer holds the file bytes in [offset, offset + filled)
+    offset: usize,
+    filled: usize,
+    len: usize,
+    capacity: usize,
+    direct: bool,
+    growth: GrowthPolicy,
+    // set once data may have been lost, see `poison`
+    poisoned: bool,
+    _marker: PhantomData<T>,
+}
+
+/// How the buffer gets to the file.
+enum Engine {
+    /// One buffer, written with `pwrite` while the caller waits.
+    Pwrite(AlignedBuf),
+    /// Several buffers, written through io_uring while the next one fills.
+    Uring(Box<Ring>),
+}
+
+/// Options for opening a [`BufferedVector`].
+#[derive(Clone, Debug)]
+pub struct BufferedOptions {
+    type_tag: u64,
+    buffer_size: usize,
+    direct: bool,
+    io_uring: bool,
+    growth_policy: GrowthPolicy,
+    capacity: Option<usize>,
+}
+
+impl Default for BufferedOptions {
+    fn default() -> Self {
+        Self {
+            type_tag: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            direct: false,
+            io_uring: false,
+            growth_policy: GrowthPolicy::default(),
+            capacity: None,
+        }
+    }
+}
+
+impl BufferedOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tag recorded in new files and checked against existing ones.
+    /// Defaults to 0.
+    pub fn type_tag(&mut self, type_tag: u64) -> &mut Self {
+        self.type_tag = type_tag;
+        self
+    }
+
+    /// Bytes buffered between writes, rounded up to a whole page. Defaults
+    /// to [`DEFAULT_BUFFER_SIZE`].
+    pub fn buffer_size(&mut self, bytes: usize) -> &mut Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Write with `O_DIRECT`, bypassing the page cache. Defaults to `false`.
+    ///
+    /// Writes are then padded to whole pages, so the last partial page is
+    /// written again on every flush until it fills. Filesystems without
+    /// `O_DIRECT` support, such as tmpfs, fail the open.
+    pub fn direct(&mut self, direct: bool) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    /// Write through io_uring. Defaults to `false`.
+    ///
+    /// Appends then fill one of several registered buffers while the ones
+    /// filled before are still being written, and
+    /// [`BufferedVector::start_flush`] submits the syncs without waiting for
+    /// them. Where io_uring is missing or disabled the vector quietly falls
+    /// back to `pwrite`; [`BufferedVector::uses_io_uring`] tells which.
+    pub fn io_uring(&mut self, io_uring: bool) -> &mut Self {
+        self.io_uring = io_uring;
+        self
+    }
+
+    /// How the file grows once the vector is full. Defaults to
+    /// [`GrowthPolicy::Doubling`].
+    pub fn growth_policy(&mut self, policy: GrowthPolicy) -> &mut Self {
+        self.growth_policy = policy;
+        self
+    }
+
+    /// Minimum capacity in elements, as for
+    /// [`VectorOptions::capacity`](crate::VectorOptions::capacity).
+    pub fn capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Opens `file` as a buffered vector of `T` with these options, taking
+    /// an exclusive lock on it.
+    pub fn open<T: Pod>(&self, file: File) -> Result<BufferedVector<T>> {
+        BufferedVector::open_with(file, self, true)
+    }
+
+    /// Like [`open`](Self::open), but fails with [`FmvError::Locked`] instead
+    /// of waiting if the file is in use.
+    pub fn try_open<T: Pod>(&self, file: File) -> Result<BufferedVector<T>> {
+        BufferedVector::open_with(file, self, false)
+    }
+}
+
+impl<T: Pod> BufferedVector<T> {
+    /// Opens `file` as a buffered vector, initializing it if it is empty.
+    ///
+    /// `file` must be opened for both reading and writing.
+    pub fn new(file: File) -> Result<Self> {
+        Self::options().open(file)
+    }
+
+    /// Options for opening a buffered vector with non-default settings.
+    pub fn options() -> BufferedOptions {
+        BufferedOptions::new()
+    }
+
+    fn open_with(file: File, opts: &BufferedOptions, blocking: bool) -> Result<Self> {
+        pod::assert_not_zero_sized::<T>();
+        if file.metadata()?.permissions().readonly() {
+            return Err(FmvError::ReadOnly);
+        }
+        // a lock of its own, as for FileMappedVector
+        let file = lock::reopen_writable(&file)?;
+        lock::lock(&file, LockMode::Exclusive, blocking)?;
+
+        let mut header = AlignedBuf::zeroed(HEADER_SIZE);
+        let fsize = file.metadata()?.len() as usize;
+        if fsize == 0 {
+            let capacity = opts.growth_policy.initial_capacity(opts.capacity);
+            header.copy_from_slice(FMVHeader::new::<T>(opts.type_tag).as_bytes());
+            file.set_len((HEADER_SIZE + capacity * size_of::<T>()) as u64)?;
+        } else if fsize < HEADER_SIZE {
+            return Err(FmvError::TruncatedHeader);
+        } else {
+            file.read_exact_at(&mut header, 0)?;
+        }
+
+        // the header is only written back once the file is open
+        let hdr = unsafe { &mut *(header.as_mut_ptr() as *mut FMVHeader) };
+        let capacity = hdr.open_for_writing::<T>(opts.type_tag, file.metadata()?.len() as usize)?;
+        let len = hdr.size(Relaxed);
+
+        if opts.direct {
+            let fd = file.as_raw_fd();
+            let ret = unsafe { fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_DIRECT) };
+            if ret != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        let buffer_size = opts.buffer_size.max(1).next_multiple_of(DIRECT_ALIGN);
+        // fall back to pwrite where io_uring is unavailable
+        let ring = match opts.io_uring {
+            true => Ring::new(&file, buffer_size, opts.direct).ok(),
+            false => None,
+        };
+        let engine = match ring {
+            Some(ring) => Engine::Uring(Box::new(ring)),
+            None => Engine::Pwrite(AlignedBuf::zeroed(buffer_size)),
+        };
+
+        let mut vec = Self {
+            engine,
+            file,
+            header,
+            offset: HEADER_SIZE + len * size_of::<T>(),
+            filled: 0,
+            len,
+            capacity,
+            direct: opts.direct,
+            growth: opts.growth_policy,
+            poisoned: false,
+            _marker: PhantomData,
+        };
+        if let Some(capacity) = opts.capacity {
+            vec.reserve_bytes(HEADER_SIZE + capacity * size_of::<T>())?;
+        }
+        vec.write_header()?;
+        if vec.direct {
+            vec.load_partial_block()?;
+        }
+
+        Ok(vec)
+    }
+
+    /// Moves the start of the buffer down to a block boundary and reads the
+    /// partial block there back in, as direct writes must start on a block
+    /// boundary and the block is written again once it fills.
+    fn load_partial_block(&mut self) -> Result<()> {
+        let partial = self.offset % DIRECT_ALIGN;
+        self.offset -= partial;
+        self.filled = partial;
+        if partial == 0 {
+            return Ok(());
+        }
+
+        let buffer = match &mut self.engine {
+            Engine::Pwrite(buffer) => buffer,
+            Engine::Uring(ring) => ring.current(),
+        };
+        let read = self
+            .file
+            .read_at(&mut buffer[..DIRECT_ALIGN], self.offset as u64)?;
+        if read < partial {
+            return Err(FmvError::SizeExceedsFile);
+        }
+        Ok(())
+    }
+
+    /// Drops every byte appended after file offset `end`, which must not be
+    /// past the end of the bytes appended so far.
+    ///
+    /// Bytes already written out are left in the file, to be overwritten by
+    /// the next appends.
+    fn rewind(&mut self, end: usize) -> Result<()> {
+        if end >= self.offset {
+            self.filled = end - self.offset;
+            return Ok(());
+        }
+
+        // the buffers no longer hold the bytes below `offset`
+        if let Engine::Uring(ring) = &mut self.engine {
+            ring.wait_all()?;
+        }
+        self.offset = end;
+        self.filled = 0;
+        if self.direct {
+            self.load_partial_block()?;
+        }
+        Ok(())
+    }
+
+    fn buffer(&mut self) -> &mut AlignedBuf {
+        match &mut self.engine {
+            Engine::Pwrite(buffer) => buffer,
+            Engine::Uring(ring) => ring.current(),
+        }
+    }
+
+    fn header(&mut self) -> &mut FMVHeader {
+        unsafe { &mut *(self.header.as_mut_ptr() as *mut FMVHeader) }
+    }
+
+    fn check_poisoned(&self) -> Result<()> {
+        match self.poisoned {
+            true => Err(FmvError::Poisoned),
+            false => Ok(()),
+        }
+    }
+
+    /// Poisons the vector if `result` failed: a failed io_uring write or a
+    /// failed sync cannot be retried, as what reached the file is unknown.
+    fn poison<R>(&mut self, result: Result<R>) -> Result<R> {
+        if result.is_err() {
+            self.poisoned = true;
+        }
+        result
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        self.file.write_all_at(&self.header, 0)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Appends `value`, writing out the buffer if it fills.
+    pub fn push(&mut self, value: T) -> Result<()> {
+        self.extend_from_slice(std::slice::from_ref(&value))
+    }
+
+    /// Appends all of `values`, writing out the buffer each time it fills.
+    ///
+    /// With plain `pwrite`, batches larger than the buffer are written
+    /// straight from `values`. If a write fails none of `values` are
+    /// appended, even those already written out.
+    pub fn extend_from_slice(&mut self, values: &[T]) -> Result<()> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, size_of_val(values))
+        };
+
+        self.check_poisoned()?;
+        let end = self.offset + self.filled;
+        if let Err(err) = self.append_bytes(bytes) {
+            // the file offset must keep matching `len`
+            if !self.poisoned {
+                let rewound = self.rewind(end);
+                self.poison(rewound)?;
+            }
+            return Err(err);
+        }
+
+        self.len += values.len();
+        Ok(())
+    }
+
+    fn append_bytes(&mut self, mut bytes: &[u8]) -> Result<()> {
+        let bypass = match &self.engine {
+            Engine::Pwrite(buffer) => !self.direct && bytes.len() >= buffer.len(),
+            Engine::Uring(_) => false,
+        };
+        if bypass && self.filled == 0 {
+            self.reserve_bytes(self.offset + bytes.len())?;
+            self.file.write_all_at(bytes, self.offset as u64)?;
+            self.offset += bytes.len();
+            bytes = &[];
+        }
+
+        while !bytes.is_empty() {
+            let filled = self.filled;
+            let buffer = self.buffer();
+            let n = bytes.len().min(buffer.len() - filled);
+            buffer[filled..filled + n].copy_from_slice(&bytes[..n]);
+            let full = filled + n == buffer.len();
+
+            self.filled += n;
+            bytes = &bytes[n..];
+            if full {
+                self.write_buffer()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes to write out of the buffer, and how many of them are final.
+    ///
+    /// With `O_DIRECT` the write is padded to whole blocks, and a trailing
+    /// partial block stays in the buffer to be written again.
+    fn extent(&self) -> (usize, usize) {
+        if self.direct {
+            let len = self.filled.next_multiple_of(DIRECT_ALIGN);
+            (len, self.filled - self.filled % DIRECT_ALIGN)
+        } else {
+            (self.filled, self.filled)
+        }
+    }
+
+    /// Writes out the buffered bytes. With io_uring the write is only
+    /// submitted.
+    fn write_buffer(&mut self) -> Result<()> {
+        if self.filled == 0 {
+            return Ok(());
+        }
+
+        let (len, written) = self.extent();
+        self.reserve_bytes(self.offset + len)?;
+
+        match &mut self.engine {
+            Engine::Pwrite(buffer) => {
+                self.file.write_all_at(&buffer[..len], self.offset as u64)?;
+                buffer.copy_within(written..self.filled, 0);
+            }
+            Engine::Uring(ring) => {
+                let submitted = ring.submit(len, self.offset, written..self.filled);
+                self.poison(submitted)?;
+            }
+        }
+
+        self.offset += written;
+        self.filled -= written;
+        Ok(())
+    }
+
+    /// Grows the file so it is at least `end` bytes long.
+    fn reserve_bytes(&mut self, end: usize) -> Result<()> {
+        let file_end = HEADER_SIZE + self.capacity * size_of::<T>();
+        if end <= file_end {
+            return Ok(());
+        }
+
+        let min_cap = (end - HEADER_SIZE).div_ceil(size_of::<T>());
+        let new_cap = self
+            .growth
+            .next_capacity(self.capacity, min_cap, size_of::<T>());
+        let size_diff = (new_cap - self.capacity) * size_of::<T>();
+
+        let ret = unsafe { fallocate(self.file.as_raw_fd(), 0, file_end as i64, size_diff as i64) };
+        if ret != 0 {
+            return Err(FmvError::FallocateFailed(FmvError::errno()));
+        }
+
+        self.capacity = new_cap;
+        Ok(())
+    }
+
+    /// Writes out the buffer and makes every element appended so far
+    /// durable.
+    ///
+    /// The data is synced before the header records the new length, so the
+    /// on-disk length never covers data that has not reached the disk.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Engine::Uring(_) = self.engine {
+            self.start_flush()?;
+            if let Engine::Uring(ring) = &mut self.engine {
+                let done = ring.wait_all();
+                self.poison(done)?;
+            }
+            return Ok(());
+        }
+
+        self.check_poisoned()?;
+        self.write_buffer()?;
+        let synced = self.file.sync_data().map_err(FmvError::from);
+        self.poison(synced)?;
+
+        let len = self.len;
+        let header = self.header();
+        header.set_size(len, Relaxed);
+        header.set_synced_size(len, Relaxed);
+        let written = self.write_header();
+        self.poison(written)
+    }
+
+    /// Starts making every element appended so far durable, without waiting
+    /// for the disk.
+    ///
+    /// With io_uring, the rest of the buffer, a data sync, the header and a
+    /// second sync are submitted as one linked chain, after the buffer writes
+    /// already in flight complete. The next flush or drop waits for the
+    /// chain and reports any error. Without io_uring this is
+    /// [`flush`](Self::flush).
+    pub fn start_flush(&mut self) -> Result<()> {
+        self.check_poisoned()?;
+        match &mut self.engine {
+            // the header is about to change, so the last chain must be done
+            // writing it
+            Engine::Uring(ring) => {
+                let done = ring.wait_sync();
+                self.poison(done)?;
+            }
+            Engine::Pwrite(_) => return self.flush(),
+        }
+
+        let (len, written) = self.extent();
+        self.reserve_bytes(self.offset + len)?;
+
+        let size = self.len;
+        let header = self.header();
+        header.set_size(size, Relaxed);
+        header.set_synced_size(size, Relaxed);
+
+        if let Engine::Uring(ring) = &mut self.engine {
+            let keep = written..self.filled;
+            let submitted = unsafe { ring.sync(len, self.offset, keep, &self.header) };
+            self.poison(submitted)?;
+        }
+        self.offset += written;
+        self.filled -= written;
+        Ok(())
+    }
+
+    /// Whether writes go through io_uring, see [`BufferedOptions::io_uring`].
+    pub fn uses_io_uring(&self) -> bool {
+        matches!(self.engine, Engine::Uring(_))
+    }
+
+    /// Number of elements appended so far, including buffered ones.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of elements the file has room for.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T: Pod> Storage for BufferedVector<T> {
+    type Item = T;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, value: T) -> Result<()> {
+        BufferedVector::push(self, value)
+    }
+
+    fn extend_from_slice(&mut self, values: &[T]) -> Result<()> {
+        BufferedVector::extend_from_slice(self, values)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        BufferedVector::flush(self)
+    }
+}
+
+impl<T: Pod> Drop for BufferedVector<T> {
+    fn drop(&mut self) {
+        // as with FileMappedVector, a failed flush leaves the file dirty so
+        // the next open rolls back to the last good length; a poisoned
+        // vector fails it too
+        if self.flush().is_ok() {
+            let header = self.header();
+            header.flags.set(header.flags.get() & !FLAG_DIRTY);
+            header.writer_pid.set(0);
+            let _ = self.write_header();
+        }
+    }
+}
+
+/// A zeroed heap buffer aligned for `O_DIRECT`.
+pub(crate) struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl AlignedBuf {
+    pub fn zeroed(len: usize) -> Self {
+        let layout = Layout::from_size_align(len, DIRECT_ALIGN).unwrap();
+        let ptr = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .unwrap_or_else(|| handle_alloc_error(layout));
+        Self { ptr, len }
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        let layout = Layout::from_size_align(self.len, DIRECT_ALIGN).unwrap();
+        unsafe { dealloc(self.ptr.as_ptr(), layout) };
+    }
+}
@@ -0,0 +1,175 @@
+//! CRC32C checksums of fixed-size data blocks, kept in a sidecar file.
+//!
+//! The sidecar starts with a 16 byte header (magic and block size), followed
+//! by one little-endian CRC32C per sealed block of the data region. A block
+//! is sealed once the vector's length reaches its end; the partial block at
+//! the tail has no checksum until then.
+
+use std::{fs::File, ops::Range, os::unix::fs::FileExt};
+
+use crate::error::{FmvError, Result};
+
+/// Magic bytes at the start of every checksum sidecar.
+pub const CHECKSUM_MAGIC: [u8; 8] = *b"FMVCRC\0\0";
+
+/// Bytes of element data covered by each checksum by default.
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+const SIDECAR_HEADER_SIZE: u64 = 16;
+
+/// Table for the bytewise CRC32C (Castagnoli) fallback.
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC32C of `data`.
+pub fn crc32c(data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("sse4.2") {
+        return unsafe { crc32c_sse42(data) };
+    }
+
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut crc = !0u64;
+    let mut words = data.chunks_exact(8);
+    for word in &mut words {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(word.try_into().unwrap()));
+    }
+
+    let mut crc = crc as u32;
+    for &byte in words.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    !crc
+}
+
+/// Checksums of the sealed blocks of one vector file.
+pub(crate) struct Checksums {
+    sidecar: File,
+    block_size: usize,
+    // number of checksums in the sidecar
+    blocks: usize,
+}
+
+impl Checksums {
+    /// Opens `sidecar`, initializing it if it is empty.
+    pub fn open(sidecar: File, block_size: usize) -> Result<Self> {
+        if sidecar.metadata()?.len() == 0 {
+            let mut header = [0; SIDECAR_HEADER_SIZE as usize];
+            header[..8].copy_from_slice(&CHECKSUM_MAGIC);
+            header[8..12].copy_from_slice(&(block_size as u32).to_le_bytes());
+            sidecar.write_all_at(&header, 0)?;
+        } else if read_block_size(&sidecar)? != block_size {
+            return Err(FmvError::BadSidecar);
+        }
+
+        let len = sidecar.metadata()?.len().max(SIDECAR_HEADER_SIZE);
+        Ok(Self {
+            sidecar,
+            block_size,
+            blocks: ((len - SIDECAR_HEADER_SIZE) / 4) as usize,
+        })
+    }
+
+    /// Brings the sidecar in line with `data`, the initialized bytes of the
+    /// data region, and syncs it.
+    ///
+    /// Blocks at or after the one containing byte `dirty_from` are
+    /// recomputed even if they already have a checksum.
+    pub fn update(&mut self, data: &[u8], dirty_from: usize) -> Result<()> {
+        let sealed = data.len() / self.block_size;
+        let from = self.blocks.min(dirty_from / self.block_size).min(sealed);
+
+        let crcs: Vec<u8> = data[from * self.block_size..sealed * self.block_size]
+            .chunks_exact(self.block_size)
+            .flat_map(|block| crc32c(block).to_le_bytes())
+            .collect();
+        self.sidecar
+            .write_all_at(&crcs, SIDECAR_HEADER_SIZE + from as u64 * 4)?;
+
+        // drop checksums of blocks the vector was truncated below
+        if self.blocks > sealed {
+            self.sidecar
+                .set_len(SIDECAR_HEADER_SIZE + sealed as u64 * 4)?;
+        }
+        self.blocks = sealed;
+
+        self.sidecar.sync_data()?;
+        Ok(())
+    }
+
+    /// Byte ranges of `data` whose sealed blocks do not match their checksum.
+    pub fn verify(&self, data: &[u8]) -> Result<Vec<Range<usize>>> {
+        verify(&self.sidecar, data)
+    }
+}
+
+/// Block size recorded in the header of `sidecar`.
+fn read_block_size(sidecar: &File) -> Result<usize> {
+    let mut header = [0; SIDECAR_HEADER_SIZE as usize];
+    sidecar.read_exact_at(&mut header, 0)?;
+
+    let block_size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+    if header[..8] != CHECKSUM_MAGIC || block_size == 0 {
+        return Err(FmvError::BadSidecar);
+    }
+    Ok(block_size)
+}
+
+/// Checks `data` against the checksums in `sidecar`, returning the byte
+/// ranges of corrupt blocks. Adjacent corrupt blocks are merged.
+pub(crate) fn verify(sidecar: &File, data: &[u8]) -> Result<Vec<Range<usize>>> {
+    let block_size = read_block_size(sidecar)?;
+    let len = sidecar.metadata()?.len();
+    let blocks = (len.saturating_sub(SIDECAR_HEADER_SIZE) / 4) as usize;
+
+    let mut crcs = vec![0; blocks * 4];
+    sidecar.read_exact_at(&mut crcs, SIDECAR_HEADER_SIZE)?;
+
+    let mut corrupt: Vec<Range<usize>> = Vec::new();
+    let checked = data.chunks_exact(block_size).zip(crcs.chunks_exact(4));
+    for (i, (block, crc)) in checked.enumerate() {
+        if crc32c(block) == u32::from_le_bytes(crc.try_into().unwrap()) {
+            continue;
+        }
+
+        let range = i * block_size..(i + 1) * block_size;
+        match corrupt.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => corrupt.push(range),
+        }
+    }
+
+    Ok(corrupt)
+}
+
+/// Converts a byte range of the data region to the range of elements of
+/// `elem_size` bytes overlapping it.
+pub(crate) fn element_range(bytes: Range<usize>, elem_size: usize) -> Range<usize> {
+    bytes.start / elem_size..bytes.end.div_ceil(elem_size)
+}
@@ -0,0 +1,107 @@
+//! Fixed little-endian storage, for files that move between machines.
+
+use std::{cmp::Ordering, fmt};
+
+use crate::pod::Pod;
+
+/// Primitives that can be converted to and from little-endian.
+pub trait Endian: Pod {
+    fn to_le(self) -> Self;
+    fn from_le(value: Self) -> Self;
+}
+
+macro_rules! impl_endian_int {
+    ($($ty:ty),*) => {
+        $(impl Endian for $ty {
+            fn to_le(self) -> Self {
+                <$ty>::to_le(self)
+            }
+
+            fn from_le(value: Self) -> Self {
+                <$ty>::from_le(value)
+            }
+        })*
+    };
+}
+
+impl_endian_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+macro_rules! impl_endian_float {
+    ($($ty:ty),*) => {
+        $(impl Endian for $ty {
+            fn to_le(self) -> Self {
+                <$ty>::from_bits(self.to_bits().to_le())
+            }
+
+            // a byte swap is its own inverse
+            fn from_le(value: Self) -> Self {
+                Endian::to_le(value)
+            }
+        })*
+    };
+}
+
+impl_endian_float!(f32, f64);
+
+/// A `T` stored little-endian regardless of the host byte order.
+///
+/// Native element types are written in the writer's byte order, and
+/// opening the file on a machine with the other order fails with
+/// [`FmvError::ByteOrderMismatch`]. Wrapping them, as in
+/// `FileMappedVector<Le<u64>>`, makes the file readable everywhere, at the
+/// cost of a byte swap per access on big-endian hosts.
+///
+/// [`FmvError::ByteOrderMismatch`]: crate::FmvError::ByteOrderMismatch
+#[derive(Clone, Copy, Default)]
+#[repr(transparent)]
+pub struct Le<T: Endian>(T);
+
+impl<T: Endian> Le<T> {
+    pub fn new(value: T) -> Self {
+        Self(value.to_le())
+    }
+
+    pub fn get(self) -> T {
+        T::from_le(self.0)
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.0 = value.to_le();
+    }
+}
+
+unsafe impl<T: Endian> Pod for Le<T> {
+    const PORTABLE: bool = true;
+}
+
+impl<T: Endian> From<T> for Le<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Endian + fmt::Debug> fmt::Debug for Le<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+impl<T: Endian + PartialEq> PartialEq for Le<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<T: Endian + Eq> Eq for Le<T> {}
+
+impl<T: Endian + PartialOrd> PartialOrd for Le<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.get().partial_cmp(&other.get())
+    }
+}
+
+impl<T: Endian + Ord> Ord for Le<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.get().cmp(&other.get())
+    }
+}
@@ -0,0 +1,141 @@
+//! Errors returned by file-mapped vectors.
+
+use std::{fmt, io};
+
+/// Everything that can go wrong opening or growing a [`FileMappedVector`].
+///
+/// [`FileMappedVector`]: crate::FileMappedVector
+#[derive(Debug)]
+pub enum FmvError {
+    /// The file does not start with [`MAGIC`](crate::MAGIC).
+    BadMagic,
+    /// The file was written by an unknown format version.
+    UnsupportedVersion(u32),
+    /// The element type recorded in the header differs from the one requested.
+    TypeMismatch {
+        field: &'static str,
+        expected: u64,
+        found: u64,
+    },
+    /// The elements were written with the other byte order and are not
+    /// portable.
+    ByteOrderMismatch,
+    /// The data region is not a whole number of elements long.
+    SizeNotMultipleOfElement,
+    /// The file is shorter than a header.
+    TruncatedHeader,
+    /// The header claims more elements than the file holds.
+    SizeExceedsFile,
+    /// The file was opened without write permission.
+    ReadOnly,
+    /// Another process holds a conflicting lock on the file. `pid` is the
+    /// writer recorded in the header, if there is one.
+    Locked {
+        pid: Option<u32>,
+    },
+    /// A checksum sidecar has a bad magic or a different block size.
+    BadSidecar,
+    /// [`verify`](crate::FileMappedVector::verify) was called without
+    /// checksums enabled.
+    ChecksumsDisabled,
+    /// An earlier write or sync of a [`BufferedVector`](crate::BufferedVector)
+    /// failed, so the file may be missing data it was told to hold. Reopening
+    /// it rolls back to the last flush.
+    Poisoned,
+    /// A point was appended to a [`Series`](crate::Series) with a timestamp
+    /// before the last one, under [`OutOfOrderPolicy::Reject`].
+    ///
+    /// [`OutOfOrderPolicy::Reject`]: crate::series::OutOfOrderPolicy::Reject
+    OutOfOrder {
+        last: i64,
+        ts: i64,
+    },
+    /// A point was appended to a [`Series`](crate::Series) with the same
+    /// timestamp as an existing one, under [`DuplicatePolicy::Error`].
+    ///
+    /// [`DuplicatePolicy::Error`]: crate::series::DuplicatePolicy::Error
+    DuplicateTimestamp(i64),
+    /// `mmap` failed with the given errno.
+    MmapFailed(i32),
+    /// `fallocate` failed with the given errno.
+    FallocateFailed(i32),
+    /// `mlock` failed with the given errno, usually `ENOMEM` from
+    /// `RLIMIT_MEMLOCK`.
+    MlockFailed(i32),
+    Io(io::Error),
+}
+
+/// Shorthand for results with a [`FmvError`].
+pub type Result<T> = std::result::Result<T, FmvError>;
+
+impl FmvError {
+    /// errno of the last failed libc call.
+    pub(crate) fn errno() -> i32 {
+        io::Error::last_os_error().raw_os_error().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for FmvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "file is not a file-mapped vector (bad magic)"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::TypeMismatch {
+                field,
+                expected,
+                found,
+            } => write!(f, "{field} mismatch: file has {found}, expected {expected}"),
+            Self::ByteOrderMismatch => {
+                write!(f, "elements were written with a different byte order")
+            }
+            Self::SizeNotMultipleOfElement => {
+                write!(f, "data size is not a multiple of the element size")
+            }
+            Self::TruncatedHeader => write!(f, "file is too short to hold a header"),
+            Self::SizeExceedsFile => write!(f, "header length exceeds the file size"),
+            Self::ReadOnly => write!(f, "file is not readable and writable"),
+            Self::Locked { pid: Some(pid) } => {
+                write!(f, "file is locked by another process (pid {pid})")
+            }
+            Self::Locked { pid: None } => write!(f, "file is locked by another process"),
+            Self::BadSidecar => write!(f, "checksum file is invalid or uses another block size"),
+            Self::ChecksumsDisabled => write!(f, "checksums are not enabled"),
+            Self::Poisoned => write!(f, "an earlier write failed, reopen the file to recover"),
+            Self::OutOfOrder { last, ts } => {
+                write!(f, "timestamp {ts} is before the last one, {last}")
+            }
+            Self::DuplicateTimestamp(ts) => write!(f, "timestamp {ts} is already in the series"),
+            Self::MmapFailed(errno) => {
+                write!(f, "mmap failed: {}", io::Error::from_raw_os_error(*errno))
+            }
+            Self::MlockFailed(errno) => {
+                write!(f, "mlock failed: {}", io::Error::from_raw_os_error(*errno))
+            }
+            Self::FallocateFailed(errno) => {
+                write!(
+                    f,
+                    "fallocate failed: {}",
+                    io::Error::from_raw_os_error(*errno)
+                )
+            }
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for FmvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FmvError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
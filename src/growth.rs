@@ -0,0 +1,52 @@
+//! How a vector's file grows when it fills up.
+
+/// Number of elements a new file is allocated with by default.
+pub(crate) const INITIAL_CAPACITY: usize = 32;
+
+/// Strategy for picking a new capacity when a [`FileMappedVector`] is full.
+///
+/// Every growth is a single `fallocate`, so fewer, larger steps mean fewer
+/// syscalls, and smaller steps mean less unused space at the end of the file.
+///
+/// [`FileMappedVector`]: crate::FileMappedVector
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    /// Double the capacity.
+    #[default]
+    Doubling,
+    /// Grow by a fixed number of bytes, rounded up to whole elements.
+    Chunk(usize),
+    /// Double the capacity, but never grow by more than this many bytes at
+    /// once.
+    CappedDoubling(usize),
+    /// Allocate room for this many elements in one go, doubling once the
+    /// vector outgrows it. New files start at this capacity.
+    Preallocate(usize),
+}
+
+impl GrowthPolicy {
+    /// Capacity a new file is created with, given the minimum `capacity`
+    /// asked for, if any.
+    pub(crate) fn initial_capacity(&self, capacity: Option<usize>) -> usize {
+        match (capacity, *self) {
+            (Some(capacity), _) => capacity,
+            (None, Self::Preallocate(expected)) => expected,
+            (None, _) => INITIAL_CAPACITY,
+        }
+    }
+
+    /// Capacity to grow to from `capacity` elements of `elem_size` bytes so
+    /// that at least `min_cap` fit.
+    pub(crate) fn next_capacity(&self, capacity: usize, min_cap: usize, elem_size: usize) -> usize {
+        let doubled = (capacity * 2).max(1);
+        let new_cap = match *self {
+            Self::Doubling => doubled,
+            Self::Chunk(bytes) => capacity + bytes.div_ceil(elem_size).max(1),
+            Self::CappedDoubling(max_step) => doubled.min(capacity + (max_step / elem_size).max(1)),
+            Self::Preallocate(expected) => doubled.max(expected),
+        };
+
+        // a single append may need more than one step
+        new_cap.max(min_cap)
+    }
+}
@@ -0,0 +1,206 @@
+//! On-disk header stored in the first page of every vector file.
+//!
+//! All header fields are fixed width and little-endian, so any host can read
+//! the header. The elements themselves are stored in the byte order recorded
+//! in [`FMVHeader::byte_order`].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    endian::Le,
+    error::{FmvError, Result},
+    pod::Pod,
+};
+
+/// Magic bytes at the start of every file-mapped vector.
+pub const MAGIC: [u8; 8] = *b"FMAPVEC\0";
+
+/// Size of [`FMVHeader`] on disk. Elements start right after it.
+pub const HEADER_SIZE: usize = 4096;
+
+/// Current on-disk format version.
+///
+/// Version 0 files predate element metadata; their reserved bytes are zero.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Set in [`FMVHeader::flags`] while a writer has the file open.
+pub const FLAG_DIRTY: u32 = 1;
+
+/// Values of [`FMVHeader::byte_order`]. Files written before it was recorded
+/// hold 0 and are treated as little-endian.
+pub const BYTE_ORDER_LITTLE: u8 = 1;
+pub const BYTE_ORDER_BIG: u8 = 2;
+
+/// Byte order of this host.
+pub const BYTE_ORDER_NATIVE: u8 = if cfg!(target_endian = "big") {
+    BYTE_ORDER_BIG
+} else {
+    BYTE_ORDER_LITTLE
+};
+
+const RESERVED_SIZE: usize = HEADER_SIZE - 8 - 8 - 4 * 4 - 8 - 8 - 4 - 4;
+
+/// Header at offset 0 of a vector file.
+#[repr(C)]
+pub struct FMVHeader {
+    pub magic: [u8; 8],
+    // number of initialized elements, see `size`
+    size: AtomicU64,
+
+    pub version: Le<u32>,
+    /// `size_of::<T>()` of the element type the file was created with.
+    pub elem_size: Le<u32>,
+    /// `align_of::<T>()` of the element type the file was created with.
+    pub elem_align: Le<u32>,
+    /// [`FLAG_DIRTY`] and friends.
+    pub flags: Le<u32>,
+    /// Caller-chosen tag identifying the element type or schema.
+    pub type_tag: Le<u64>,
+    // length at the last flush, see `synced_size`
+    synced_size: AtomicU64,
+    /// PID of the process holding the file open for writing, 0 if none.
+    pub writer_pid: Le<u32>,
+    /// Byte order of the elements, one of the `BYTE_ORDER_*` constants.
+    pub byte_order: u8,
+    _pad: [u8; 3],
+
+    // header should span a page (4K)
+    pub reserved: [u8; RESERVED_SIZE],
+}
+
+const _: () = assert!(size_of::<FMVHeader>() == HEADER_SIZE);
+
+impl FMVHeader {
+    /// An empty header for a vector of `T`.
+    pub fn new<T: Pod>(type_tag: u64) -> Self {
+        Self {
+            magic: MAGIC,
+            size: AtomicU64::new(0),
+            version: Le::new(FORMAT_VERSION),
+            elem_size: Le::new(size_of::<T>() as u32),
+            elem_align: Le::new(align_of::<T>() as u32),
+            flags: Le::new(0),
+            type_tag: Le::new(type_tag),
+            synced_size: AtomicU64::new(0),
+            writer_pid: Le::new(0),
+            byte_order: BYTE_ORDER_NATIVE,
+            _pad: [0; 3],
+            reserved: [0; RESERVED_SIZE],
+        }
+    }
+
+    /// Raw bytes of the header, as written to disk.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, HEADER_SIZE) }
+    }
+
+    /// Number of initialized elements. Updated on every push, so it may run
+    /// ahead of what has reached the disk.
+    pub fn size(&self, order: Ordering) -> usize {
+        u64::from_le(self.size.load(order)) as usize
+    }
+
+    pub(crate) fn set_size(&self, size: usize, order: Ordering) {
+        self.size.store((size as u64).to_le(), order);
+    }
+
+    /// Length at the last flush. Everything below it is known to be on disk.
+    pub fn synced_size(&self, order: Ordering) -> usize {
+        u64::from_le(self.synced_size.load(order)) as usize
+    }
+
+    pub(crate) fn set_synced_size(&self, size: usize, order: Ordering) {
+        self.synced_size.store((size as u64).to_le(), order);
+    }
+
+    /// Checks that the file holds elements of type `T` tagged `type_tag`,
+    /// stored in a byte order this host can read.
+    pub fn validate<T: Pod>(&self, type_tag: u64) -> Result<()> {
+        if self.magic != MAGIC {
+            return Err(FmvError::BadMagic);
+        }
+        if self.version.get() != FORMAT_VERSION {
+            return Err(FmvError::UnsupportedVersion(self.version.get()));
+        }
+
+        let checks = [
+            (
+                "element size",
+                size_of::<T>() as u64,
+                self.elem_size.get() as u64,
+            ),
+            (
+                "element alignment",
+                align_of::<T>() as u64,
+                self.elem_align.get() as u64,
+            ),
+            ("type tag", type_tag, self.type_tag.get()),
+        ];
+        for (field, expected, found) in checks {
+            if expected != found {
+                return Err(FmvError::TypeMismatch {
+                    field,
+                    expected,
+                    found,
+                });
+            }
+        }
+
+        let byte_order = match self.byte_order {
+            0 => BYTE_ORDER_LITTLE,
+            byte_order => byte_order,
+        };
+        if byte_order != BYTE_ORDER_NATIVE && !T::PORTABLE {
+            return Err(FmvError::ByteOrderMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Prepares a header read from a file of `file_size` bytes for a writer
+    /// of `T`, returning the capacity in elements.
+    ///
+    /// Fills in version 0 metadata, validates the header and the file size,
+    /// rolls a file left dirty back to its synced length, and marks it dirty
+    /// and owned by this process. Callers work on a copy and only write it
+    /// back once this succeeds, so a failed open leaves the file untouched.
+    pub(crate) fn open_for_writing<T: Pod>(
+        &mut self,
+        type_tag: u64,
+        file_size: usize,
+    ) -> Result<usize> {
+        debug_assert!(file_size >= HEADER_SIZE);
+        if self.magic == MAGIC && self.version.get() == 0 {
+            self.upgrade::<T>(type_tag);
+        }
+        self.validate::<T>(type_tag)?;
+
+        let data_size = file_size - HEADER_SIZE;
+        if !data_size.is_multiple_of(size_of::<T>()) {
+            return Err(FmvError::SizeNotMultipleOfElement);
+        }
+        let capacity = data_size / size_of::<T>();
+
+        // recover from an unclean shutdown
+        if self.flags.get() & FLAG_DIRTY != 0 {
+            self.set_size(self.synced_size(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        let size = self.size(Ordering::Relaxed);
+        if size > capacity || self.synced_size(Ordering::Relaxed) > size {
+            return Err(FmvError::SizeExceedsFile);
+        }
+
+        self.flags.set(self.flags.get() | FLAG_DIRTY);
+        self.writer_pid.set(std::process::id());
+        Ok(capacity)
+    }
+
+    /// Fills in element metadata on a version 0 header.
+    pub(crate) fn upgrade<T: Pod>(&mut self, type_tag: u64) {
+        debug_assert_eq!(self.version.get(), 0);
+        let size = self.size(Ordering::Relaxed);
+        *self = Self::new::<T>(type_tag);
+        self.set_size(size, Ordering::Relaxed);
+        self.set_synced_size(size, Ordering::Relaxed);
+    }
+}
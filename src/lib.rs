@@ -0,0 +1,54 @@
+//! A growable vector of plain-old-data values backed by a memory-mapped
+//! file.
+//!
+//! The file starts with a one page [`FMVHeader`] followed by the elements,
+//! laid out contiguously. The whole file is mapped into a large
+//! [`MMAP_SIZE`] reservation so the vector can usually grow in place without
+//! remapping; [`VectorOptions`] makes the reservation smaller.
+//!
+//! Readers that never write can use [`FileMappedSlice`] instead, which maps
+//! the file read-only, or [`FileMappedReader`] to follow a file while another
+//! process is still appending to it.
+//!
+//! [`BufferedVector`] writes the same files with `pwrite` or io_uring
+//! instead of a mapping; both implement [`Storage`].
+//!
+//! [`Series`] builds a time series out of two vectors, one for timestamps
+//! and one for values. [`aggregate`] summarises one over fixed time windows.
+
+pub mod access;
+pub mod aggregate;
+pub mod buffered;
+pub mod checksum;
+pub mod endian;
+pub mod error;
+pub mod growth;
+pub mod header;
+mod lock;
+pub mod mapping;
+pub mod options;
+pub mod pod;
+pub mod reader;
+pub mod series;
+pub mod slice;
+pub mod storage;
+pub mod sync;
+mod uring;
+pub mod vector;
+
+pub use access::Access;
+pub use aggregate::{Aggregated, Aggregation};
+pub use buffered::{BufferedOptions, BufferedVector};
+pub use endian::Le;
+pub use error::{FmvError, Result};
+pub use growth::GrowthPolicy;
+pub use header::{FMVHeader, MAGIC};
+pub use mapping::MMAP_SIZE;
+pub use options::VectorOptions;
+pub use pod::Pod;
+pub use reader::FileMappedReader;
+pub use series::{DuplicatePolicy, OutOfOrderPolicy, Points, Series};
+pub use slice::FileMappedSlice;
+pub use storage::Storage;
+pub use sync::SyncPolicy;
+pub use vector::FileMappedVector;
@@ -0,0 +1,111 @@
+//! Advisory `flock`s keeping writers exclusive.
+
+use std::{
+    fs::File,
+    io,
+    mem::offset_of,
+    os::{fd::AsRawFd, unix::fs::FileExt},
+};
+
+use libc::{
+    fcntl, flock, EWOULDBLOCK, F_GETFL, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN, O_ACCMODE, O_RDWR,
+};
+
+use crate::{
+    error::{FmvError, Result},
+    header::FMVHeader,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LockMode {
+    /// Held by the single writer.
+    Exclusive,
+    /// Held by any number of readers.
+    Shared,
+}
+
+/// Locks `file`, waiting for other holders unless `blocking` is false.
+///
+/// The lock belongs to the open file description, so it lasts until every
+/// descriptor sharing it is closed or [`unlock`] is called.
+pub(crate) fn lock(file: &File, mode: LockMode, blocking: bool) -> Result<()> {
+    let mut op = match mode {
+        LockMode::Exclusive => LOCK_EX,
+        LockMode::Shared => LOCK_SH,
+    };
+    if !blocking {
+        op |= LOCK_NB;
+    }
+
+    loop {
+        if unsafe { flock(file.as_raw_fd(), op) } == 0 {
+            return Ok(());
+        }
+
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(EWOULDBLOCK) => {
+                return Err(FmvError::Locked {
+                    pid: writer_pid(file),
+                })
+            }
+            _ => return Err(err.into()),
+        }
+    }
+}
+
+pub(crate) fn unlock(file: &File) {
+    unsafe { flock(file.as_raw_fd(), LOCK_UN) };
+}
+
+/// Opens the file behind `file` again, read-only, as a new open file
+/// description with locks of its own.
+pub(crate) fn reopen(file: &File) -> Result<File> {
+    Ok(File::open(proc_path(file))?)
+}
+
+/// Like [`reopen`], but for reading and writing. Fails with
+/// [`FmvError::ReadOnly`] unless `file` itself is open for both.
+pub(crate) fn reopen_writable(file: &File) -> Result<File> {
+    let flags = unsafe { fcntl(file.as_raw_fd(), F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    if flags & O_ACCMODE != O_RDWR {
+        return Err(FmvError::ReadOnly);
+    }
+
+    Ok(File::options()
+        .read(true)
+        .write(true)
+        .open(proc_path(file))?)
+}
+
+fn proc_path(file: &File) -> String {
+    format!("/proc/self/fd/{}", file.as_raw_fd())
+}
+
+/// Whether a writer holds `file`, which must not be locked itself.
+pub(crate) fn has_writer(file: &File) -> Result<bool> {
+    match lock(file, LockMode::Shared, false) {
+        Ok(()) => {
+            unlock(file);
+            Ok(false)
+        }
+        Err(FmvError::Locked { .. }) => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+/// PID of the writer recorded in the header of `file`, if any.
+fn writer_pid(file: &File) -> Option<u32> {
+    let mut pid = [0; 4];
+    file.read_exact_at(&mut pid, offset_of!(FMVHeader, writer_pid) as u64)
+        .ok()?;
+
+    match u32::from_le_bytes(pid) {
+        0 => None,
+        pid => Some(pid),
+    }
+}
@@ -1,197 +1,60 @@
-use std::{
-    fs::File,
-    io::{Seek, SeekFrom, Write},
-    os::{fd::AsRawFd, raw::c_void},
-    process::exit,
-};
+use std::fs::File;
 
-use libc::{
-    fallocate, madvise, mmap, msync, munmap, MADV_DONTNEED, MADV_RANDOM, MADV_SEQUENTIAL,
-    MADV_WILLNEED, MAP_SHARED, MS_ASYNC, MS_SYNC, PROT_READ, PROT_WRITE,
-};
+use tsdb_rs::{BufferedOptions, BufferedVector, FileMappedVector, Storage, VectorOptions};
 
-const MAGIC: [u8; 8] = *b"FMAPVEC\0";
-const MMAP_SIZE: usize = 1 << 40; // 1 TiB
+const N: u64 = 500_000_000;
+const BATCH: u64 = 1_000_000;
+const READS: u64 = 10_000_000;
 
-#[repr(C)]
-struct FMVHeader {
-    magic: [u8; 8],
-    size: usize,
-
-    // header should span a page (4K)
-    reserved: [u8; 4096 - 8 - size_of::<usize>()],
-}
-
-struct FileMappedVector<T: Copy> {
-    file: File,
-    capacity: usize,
-
-    header: *mut FMVHeader,
-    data: *mut T,
+fn fresh_file(fname: &str) -> anyhow::Result<File> {
+    File::create(fname)?;
+    Ok(File::options().read(true).write(true).open(fname)?)
 }
 
-impl<T: Copy> FileMappedVector<T> {
-    pub fn new(mut file: File) -> anyhow::Result<Self> {
-        assert!(size_of::<FMVHeader>() == 4096);
-
-        if file.metadata().unwrap().permissions().readonly() {
-            return Err(anyhow::anyhow!("file is not readable and writable"));
-        }
-
-        let init_cap = size_of::<T>() * 32;
-        let initial_file_size = size_of::<FMVHeader>() + init_cap;
-
-        // open file, initialize if new file
-        let fsize = file.metadata().unwrap().len();
-        if fsize == 0 {
-            file.set_len(initial_file_size as u64).unwrap();
-
-            // initialize header
-            let header = FMVHeader {
-                magic: MAGIC,
-                size: 0,
-                reserved: [0; 4096 - 8 - size_of::<usize>()],
-            };
-
-            // write header to file
-            file.seek(SeekFrom::Start(0)).unwrap();
-            file.write_all(unsafe {
-                std::slice::from_raw_parts(
-                    &header as *const FMVHeader as *const u8,
-                    size_of::<FMVHeader>(),
-                )
-            })
-            .unwrap();
-            file.seek(SeekFrom::Start(0)).unwrap();
-        }
-
-        // do some checks
-        let fsize = file.metadata().unwrap().len() as usize;
-        assert!(fsize >= size_of::<FMVHeader>());
-
-        let fsize = fsize - size_of::<FMVHeader>();
-        assert!(fsize % size_of::<T>() == 0);
-        let capacity = fsize / size_of::<T>();
-
-        // mmap file
-        let header = unsafe {
-            mmap(
-                std::ptr::null_mut(),
-                MMAP_SIZE,
-                PROT_READ | PROT_WRITE,
-                MAP_SHARED,
-                file.as_raw_fd(),
-                0,
-            )
-        };
-
-        if (header as isize) == -1 {
-            unsafe { libc::perror("mmap\0".as_ptr() as *const i8) };
-            return Err(anyhow::anyhow!("mmap failed"));
-        }
-
-        let header = header as *mut FMVHeader;
-        assert!(header != std::ptr::null_mut());
-
-        let data = unsafe { (header as *const u8).add(size_of::<FMVHeader>()) } as *mut T;
-
-        // madvise
-        unsafe {
-            let header = header as *mut c_void;
-            let data = data as *mut c_void;
-
-            madvise(header, size_of::<FMVHeader>(), MADV_WILLNEED);
-            madvise(data, capacity * size_of::<T>(), MADV_WILLNEED);
-            madvise(data.add(capacity), MMAP_SIZE - capacity * size_of::<T>(), MADV_DONTNEED);
-        };
-
-        Ok(Self {
-            file,
-            capacity,
-            header,
-            data,
-        })
-    }
-
-    pub fn push(&mut self, value: T) {
-        assert!(!self.header.is_null());
-        assert!(!self.data.is_null());
-
-        let header: &mut FMVHeader = unsafe { self.header.as_mut().unwrap() };
-        assert!(header.magic == MAGIC);
-
-        if header.size >= self.capacity {
-            let new_cap = self.capacity * 2;
-            let old_size = size_of::<T>() * self.capacity + size_of::<FMVHeader>();
-            let size_diff = size_of::<T>() * (new_cap - self.capacity);
-
-            // self.file.set_len(new_size as u64).unwrap();
-            let ret = unsafe {
-                fallocate(
-                    self.file.as_raw_fd(),
-                    0,
-                    old_size as i64,
-                    size_diff as i64,
-                )
-            };
-            if ret != 0 {
-                unsafe { libc::perror("fallocate\0".as_ptr() as *const i8) };
-            }
-
-            unsafe {
-                let header = self.header as *mut c_void;
-
-                let old_chunk = self.data as *mut c_void;
-                let new_chunk = self.data.add(self.capacity) as *mut c_void;
-
-                madvise(header, size_of::<FMVHeader>(), MADV_DONTNEED);
-
-                madvise(old_chunk, self.capacity * size_of::<T>(), MADV_RANDOM);
-                madvise(new_chunk, size_diff, MADV_SEQUENTIAL);
-            }
-
-            self.capacity = new_cap;
-        }
-
-        unsafe {
-            self.data.add(header.size).write(value);
-        }
-
-        header.size += 1;
-    }
-
-    pub fn len(&self) -> usize {
-        unsafe { self.header.as_ref().unwrap().size }
-    }
-}
-
-impl<T: Copy> std::ops::Index<usize> for FileMappedVector<T> {
-    type Output = T;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        assert!(index < self.capacity);
-        unsafe { self.data.add(index).as_ref().unwrap() }
+/// Appends `N` elements in batches of `BATCH`, timing until the storage is
+/// dropped and everything is on disk.
+fn extend_batches(label: &str, mut storage: impl Storage<Item = u64>) -> anyhow::Result<()> {
+    let mut batch = Vec::with_capacity(BATCH as usize);
+    let start_time = std::time::Instant::now();
+    for start in (0..N).step_by(BATCH as usize) {
+        batch.clear();
+        batch.extend(start..start + BATCH);
+        storage.extend_from_slice(&batch)?;
     }
+    drop(storage);
+    println!(
+        "extend_from_slice ({label}): wrote in {:?}",
+        start_time.elapsed()
+    );
+    Ok(())
 }
 
-impl<T: Copy> Drop for FileMappedVector<T> {
-    fn drop(&mut self) {
-        unsafe {
-            msync(self.header as *mut c_void, MMAP_SIZE, MS_SYNC);
-            munmap(self.header as *mut c_void, MMAP_SIZE);
-        }
+/// Sums `READS` elements at random indices, which with 4 KiB pages misses
+/// the TLB on nearly every read.
+fn random_reads(fname: &str, label: &str, opts: &VectorOptions) -> anyhow::Result<()> {
+    let file = File::options().read(true).write(true).open(fname)?;
+    let start_time = std::time::Instant::now();
+    let vec = opts.open::<u64>(file)?;
+    let opened = start_time.elapsed();
 
-        self.file.sync_all().unwrap();
+    // xorshift64
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut sum = 0u64;
+    let start_time = std::time::Instant::now();
+    for _ in 0..READS {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        sum = sum.wrapping_add(vec[(state % vec.len() as u64) as usize]);
     }
+    println!(
+        "random reads ({label}): opened in {opened:?}, read in {:?}; output={sum}",
+        start_time.elapsed()
+    );
+    Ok(())
 }
 
-fn main() {
-    let fname = "./test-files/fmapvec";
-
-    File::create(fname).unwrap();
-    let file = File::options().read(true).write(true).open(fname).unwrap();
-    let mut vec = FileMappedVector::<u64>::new(file).unwrap();
-
+fn main() -> anyhow::Result<()> {
     // print my pid
     println!("My PID: {}", unsafe { libc::getpid() });
 
@@ -199,12 +62,43 @@ fn main() {
     // let mut input = String::new();
     // std::io::stdin().read_line(&mut input).unwrap();
 
+    // one element at a time
+    let mut vec = FileMappedVector::new(fresh_file("./test-files/fmapvec")?)?;
     let start_time = std::time::Instant::now();
-    for i in 0..500_000_000 {
-        vec.push(i);
+    for i in 0..N {
+        vec.push(i)?;
     }
     drop(vec);
-    println!("Wrote in: {:?}", start_time.elapsed());
+    println!("push: wrote in {:?}", start_time.elapsed());
+
+    // batches, as an ingest path would see them, through each backend
+    let file = fresh_file("./test-files/fmapvec-extend")?;
+    extend_batches("mmap", FileMappedVector::new(file)?)?;
+    let file = fresh_file("./test-files/fmapvec-pwrite")?;
+    extend_batches("pwrite", BufferedVector::new(file)?)?;
+    let file = fresh_file("./test-files/fmapvec-direct")?;
+    match BufferedOptions::new().direct(true).open::<u64>(file) {
+        Ok(vec) => extend_batches("pwrite + O_DIRECT", vec)?,
+        Err(err) => println!("pwrite + O_DIRECT: skipped, {err}"),
+    }
+    let file = fresh_file("./test-files/fmapvec-uring")?;
+    let vec = BufferedOptions::new().io_uring(true).open::<u64>(file)?;
+    let label = match vec.uses_io_uring() {
+        true => "io_uring",
+        false => "io_uring unavailable, pwrite",
+    };
+    extend_batches(label, vec)?;
+
+    // random reads over the same data with different page setups
+    let fname = "./test-files/fmapvec-extend";
+    random_reads(fname, "default", &VectorOptions::new())?;
+    random_reads(fname, "populate", VectorOptions::new().populate(true))?;
+    random_reads(fname, "huge pages", VectorOptions::new().huge_pages(true))?;
+    random_reads(
+        fname,
+        "huge pages + populate",
+        VectorOptions::new().huge_pages(true).populate(true),
+    )?;
 
     // let file = File::options().read(true).write(true).open(fname).unwrap();
     // let vec = FileMappedVector::<u64>::new(file).unwrap();
@@ -216,4 +110,6 @@ fn main() {
     // }
 
     // println!("Read in: {:?}; output={}", start.elapsed(), read);
+
+    Ok(())
 }
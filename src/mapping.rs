@@ -0,0 +1,150 @@
+//! Owned `mmap` regions.
+
+use std::{
+    ops::Range,
+    os::{fd::RawFd, raw::c_void},
+};
+
+use libc::{
+    madvise, mlock, mmap, mremap, msync, munlock, munmap, MADV_NORMAL, MAP_FAILED, MAP_SHARED,
+    MREMAP_MAYMOVE,
+};
+
+use crate::{
+    access::Access,
+    error::{FmvError, Result},
+    header::HEADER_SIZE,
+};
+
+/// Virtual address space reserved for a vector by default, regardless of file
+/// size.
+pub const MMAP_SIZE: usize = 1 << 40; // 1 TiB
+
+/// Size of a virtual memory page.
+pub(crate) fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// `msync` the pages covering `len` bytes starting `offset` bytes after
+/// `base`.
+///
+/// # Safety
+///
+/// `base` must be the page-aligned start of a mapping at least
+/// `offset + len` bytes long.
+pub(crate) unsafe fn sync_range(
+    base: *mut u8,
+    offset: usize,
+    len: usize,
+    flags: i32,
+) -> Result<()> {
+    let start = offset - offset % page_size();
+    let ret = msync(base.add(start) as *mut c_void, offset + len - start, flags);
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// A shared file mapping, unmapped on drop.
+pub(crate) struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl Mapping {
+    /// Maps `len` bytes of `fd` starting at offset 0. `flags` are added to
+    /// `MAP_SHARED`.
+    pub fn new(fd: RawFd, len: usize, prot: i32, flags: i32) -> Result<Self> {
+        let ptr = unsafe { mmap(std::ptr::null_mut(), len, prot, MAP_SHARED | flags, fd, 0) };
+
+        if ptr == MAP_FAILED || ptr.is_null() {
+            return Err(FmvError::MmapFailed(FmvError::errno()));
+        }
+
+        Ok(Self { ptr, len })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Resizes the mapping to `len` bytes, possibly moving it.
+    ///
+    /// Resets all `madvise` hints on the mapping and unlocks it.
+    pub fn remap(&mut self, len: usize) -> Result<()> {
+        // differing advice or locking splits the mapping into several VMAs,
+        // and mremap only moves one
+        self.advise(0, self.len, MADV_NORMAL);
+        self.unlock(0, self.len);
+
+        let ptr = unsafe { mremap(self.ptr, self.len, len, MREMAP_MAYMOVE) };
+        if ptr == MAP_FAILED {
+            return Err(FmvError::MmapFailed(FmvError::errno()));
+        }
+
+        self.ptr = ptr;
+        self.len = len;
+        Ok(())
+    }
+
+    /// `madvise` over `len` bytes starting `offset` bytes into the mapping.
+    pub fn advise(&self, offset: usize, len: usize, advice: i32) {
+        debug_assert!(offset + len <= self.len);
+        unsafe { madvise(self.ptr.add(offset), len, advice) };
+    }
+
+    /// Applies `access` to the elements of `elem_size` bytes in `range`,
+    /// stored right after the header, extending the range down to a page
+    /// boundary and reporting errors.
+    pub fn advise_elements(
+        &self,
+        range: Range<usize>,
+        elem_size: usize,
+        access: Access,
+    ) -> Result<()> {
+        let offset = HEADER_SIZE + range.start * elem_size;
+        let len = range.len() * elem_size;
+        debug_assert!(offset + len <= self.len);
+
+        let start = offset - offset % page_size();
+        if unsafe { madvise(self.ptr.add(start), offset + len - start, access.advice()) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    /// `mlock` the pages covering `len` bytes starting `offset` bytes into
+    /// the mapping.
+    pub fn lock(&self, offset: usize, len: usize) -> Result<()> {
+        debug_assert!(offset + len <= self.len);
+        if unsafe { mlock(self.ptr.add(offset), len) } != 0 {
+            return Err(FmvError::MlockFailed(FmvError::errno()));
+        }
+        Ok(())
+    }
+
+    /// `munlock` the pages covering `len` bytes starting `offset` bytes into
+    /// the mapping.
+    pub fn unlock(&self, offset: usize, len: usize) {
+        debug_assert!(offset + len <= self.len);
+        unsafe { munlock(self.ptr.add(offset), len) };
+    }
+
+    /// `msync` the pages covering `len` bytes starting `offset` bytes into the
+    /// mapping.
+    pub fn sync_range(&self, offset: usize, len: usize, flags: i32) -> Result<()> {
+        debug_assert!(offset + len <= self.len);
+        unsafe { sync_range(self.as_ptr(), offset, len, flags) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { munmap(self.ptr, self.len) };
+    }
+}
@@ -0,0 +1,148 @@
+//! Builder for opening a [`FileMappedVector`] with non-default settings.
+
+use std::fs::File;
+
+use crate::{
+    error::Result, growth::GrowthPolicy, mapping::MMAP_SIZE, pod::Pod, sync::SyncPolicy,
+    vector::FileMappedVector,
+};
+
+/// Options for opening a [`FileMappedVector`], in the style of
+/// [`std::fs::OpenOptions`].
+#[derive(Clone, Debug)]
+pub struct VectorOptions {
+    pub(crate) type_tag: u64,
+    pub(crate) reservation: usize,
+    pub(crate) noreserve: bool,
+    pub(crate) sync_policy: SyncPolicy,
+    pub(crate) growth_policy: GrowthPolicy,
+    pub(crate) capacity: Option<usize>,
+    pub(crate) huge_pages: bool,
+    pub(crate) populate: bool,
+    pub(crate) lock_tail: usize,
+}
+
+impl Default for VectorOptions {
+    fn default() -> Self {
+        Self {
+            type_tag: 0,
+            reservation: MMAP_SIZE,
+            noreserve: false,
+            sync_policy: SyncPolicy::default(),
+            growth_policy: GrowthPolicy::default(),
+            capacity: None,
+            huge_pages: false,
+            populate: false,
+            lock_tail: 0,
+        }
+    }
+}
+
+impl VectorOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tag recorded in new files and checked against existing ones.
+    /// Defaults to 0.
+    pub fn type_tag(&mut self, type_tag: u64) -> &mut Self {
+        self.type_tag = type_tag;
+        self
+    }
+
+    /// Bytes of virtual address space to map up front, header included.
+    /// Defaults to [`MMAP_SIZE`].
+    ///
+    /// A smaller reservation works where address space is limited; once the
+    /// file outgrows it the mapping is `mremap`ed to a larger region, which
+    /// may move it. Files already larger than the reservation are mapped
+    /// whole. Also used by [`FileMappedReader::with_options`].
+    ///
+    /// [`FileMappedReader::with_options`]: crate::FileMappedReader::with_options
+    pub fn reservation(&mut self, bytes: usize) -> &mut Self {
+        self.reservation = bytes;
+        self
+    }
+
+    /// Map with `MAP_NORESERVE`, so the reservation is not charged against
+    /// the commit limit when overcommit is restricted. Defaults to `false`.
+    pub fn noreserve(&mut self, noreserve: bool) -> &mut Self {
+        self.noreserve = noreserve;
+        self
+    }
+
+    /// Sync policy the vector starts with. Defaults to
+    /// [`SyncPolicy::OnDrop`].
+    pub fn sync_policy(&mut self, policy: SyncPolicy) -> &mut Self {
+        self.sync_policy = policy;
+        self
+    }
+
+    /// How the file grows once the vector is full. Defaults to
+    /// [`GrowthPolicy::Doubling`].
+    pub fn growth_policy(&mut self, policy: GrowthPolicy) -> &mut Self {
+        self.growth_policy = policy;
+        self
+    }
+
+    /// Minimum capacity in elements. New files are created with it and
+    /// smaller existing files are grown to it. By default new files start at
+    /// 32 elements, or at the expected size of
+    /// [`GrowthPolicy::Preallocate`].
+    pub fn capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Ask for transparent huge pages with `MADV_HUGEPAGE` over the whole
+    /// mapping. Defaults to `false`.
+    ///
+    /// Huge pages cut TLB misses on random reads, but the kernel only backs
+    /// file mappings with them on filesystems that support it, such as tmpfs;
+    /// elsewhere this has no effect.
+    pub fn huge_pages(&mut self, huge_pages: bool) -> &mut Self {
+        self.huge_pages = huge_pages;
+        self
+    }
+
+    /// Map with `MAP_POPULATE`, faulting in the current capacity when the
+    /// vector is opened rather than on first access. Defaults to `false`.
+    ///
+    /// Space added by later growth is faulted in as usual.
+    pub fn populate(&mut self, populate: bool) -> &mut Self {
+        self.populate = populate;
+        self
+    }
+
+    /// Keep about `bytes` of data on either side of the end of the vector
+    /// `mlock`ed, so recent elements stay resident and appends do not fault.
+    /// Defaults to 0, which locks nothing.
+    ///
+    /// The locked window follows the end as elements are appended. Locking
+    /// is limited by `RLIMIT_MEMLOCK`; exceeding it fails the open with
+    /// [`FmvError::MlockFailed`]. If moving the window fails later on, the
+    /// append still succeeds, the window stops moving, and the next
+    /// [`flush`](FileMappedVector::flush) reports the error.
+    ///
+    /// [`FmvError::MlockFailed`]: crate::FmvError::MlockFailed
+    pub fn lock_tail(&mut self, bytes: usize) -> &mut Self {
+        self.lock_tail = bytes;
+        self
+    }
+
+    /// Maps `file` as a vector of `T` with these options.
+    ///
+    /// Takes an exclusive lock on `file`, waiting for any other writer or
+    /// reader to let go of it first.
+    pub fn open<T: Pod>(&self, file: File) -> Result<FileMappedVector<T>> {
+        FileMappedVector::open_with(file, self, true)
+    }
+
+    /// Like [`open`](Self::open), but fails with [`FmvError::Locked`] instead
+    /// of waiting if the file is in use.
+    ///
+    /// [`FmvError::Locked`]: crate::FmvError::Locked
+    pub fn try_open<T: Pod>(&self, file: File) -> Result<FileMappedVector<T>> {
+        FileMappedVector::open_with(file, self, false)
+    }
+}
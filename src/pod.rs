@@ -0,0 +1,110 @@
+//! Element types that can be read back from arbitrary file contents.
+
+/// Types that are plain old data: any bit pattern of the right size is a
+/// valid value.
+///
+/// A mapped file is just bytes, and may have been written by another program
+/// or damaged on disk, so the vectors only hold `Pod` types. Reading a `bool`
+/// or an enum from a byte that is not a valid discriminant would be undefined
+/// behaviour; so would reading a pointer or reference.
+///
+/// Implemented for the integer and float primitives and arrays of `Pod`
+/// types. Structs can be declared with [`pod!`](crate::pod!), which checks
+/// the requirements below at compile time.
+///
+/// Zero-sized types such as `[u64; 0]` are `Pod`, but a file cannot hold
+/// them, so mapping one fails to compile:
+///
+/// ```compile_fail
+/// let file = std::fs::File::open("/dev/null").unwrap();
+/// let _ = tsdb_rs::FileMappedSlice::<[u64; 0]>::new(&file);
+/// ```
+///
+/// # Safety
+///
+/// Implementors must:
+///
+/// - accept every bit pattern as a valid value,
+/// - have no padding bytes, so the on-disk bytes are fully initialized,
+/// - contain no pointers, references or other process-local state, and
+/// - have a stable layout, i.e. be a primitive, an array or `#[repr(C)]`.
+pub unsafe trait Pod: Copy + 'static {
+    /// Whether the bytes mean the same thing on every platform, i.e. do not
+    /// depend on byte order or pointer width. Files of non-portable types
+    /// can only be opened on a host with the writer's byte order.
+    const PORTABLE: bool = false;
+}
+
+/// Fails to compile for zero-sized `T`, whose elements a file could not
+/// count.
+pub(crate) const fn assert_not_zero_sized<T>() {
+    const { assert!(size_of::<T>() != 0, "zero-sized types cannot be mapped") }
+}
+
+macro_rules! impl_pod {
+    ($portable:literal: $($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {
+            const PORTABLE: bool = $portable;
+        })*
+    };
+}
+
+impl_pod!(true: u8, i8);
+impl_pod!(false: u16, u32, u64, u128, usize, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {
+    const PORTABLE: bool = T::PORTABLE;
+}
+
+/// Declares a `#[repr(C)]` struct and implements [`Pod`] for it.
+///
+/// Every field must be `Pod` and the fields must add up to the size of the
+/// struct, so that there is no padding, and the struct must not be empty;
+/// all of this is checked at compile time.
+/// The struct is portable if all of its fields are.
+///
+/// ```
+/// tsdb_rs::pod! {
+///     #[derive(Debug, PartialEq)]
+///     pub struct Sample {
+///         pub ts: i64,
+///         pub value: f64,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! pod {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$fmeta:meta])* $fvis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        $vis struct $name {
+            $($(#[$fmeta])* $fvis $field: $ty),*
+        }
+
+        const _: () = {
+            fn assert_pod<T: $crate::Pod>() {}
+            let _ = || {
+                $(assert_pod::<$ty>();)*
+            };
+
+            assert!(
+                ::std::mem::size_of::<$name>() == 0 $(+ ::std::mem::size_of::<$ty>())*,
+                concat!(stringify!($name), " has padding"),
+            );
+            assert!(
+                ::std::mem::size_of::<$name>() != 0,
+                concat!(stringify!($name), " is zero-sized"),
+            );
+        };
+
+        unsafe impl $crate::Pod for $name {
+            const PORTABLE: bool = true $(&& <$ty as $crate::Pod>::PORTABLE)*;
+        }
+    };
+}
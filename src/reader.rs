@@ -0,0 +1,196 @@
+//! Following a vector file while another process appends to it.
+
+use std::{
+    fs::File,
+    marker::PhantomData,
+    ops::Range,
+    os::fd::AsRawFd,
+    sync::atomic::Ordering::Acquire,
+    time::{Duration, Instant},
+};
+
+use libc::{MAP_NORESERVE, PROT_READ};
+
+use crate::{
+    access::Access,
+    error::{FmvError, Result},
+    header::{FMVHeader, FLAG_DIRTY, HEADER_SIZE, MAGIC},
+    lock,
+    mapping::{page_size, Mapping},
+    options::VectorOptions,
+    pod::{self, Pod},
+};
+
+/// Longest sleep between polls in [`FileMappedReader::wait_for_len`].
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A read-only handle that sees elements as a live writer appends them.
+///
+/// Unlike [`FileMappedSlice`], a reader takes no lock, so it can be opened
+/// while a [`FileMappedVector`] in this or another process is writing. The
+/// writer publishes its length with release ordering after the elements
+/// below it are written, and the reader loads it with acquire ordering, so
+/// everything below [`len`](Self::len) is always fully written.
+///
+/// The file is mapped into a large reservation up front so growth is seen
+/// without remapping; [`with_options`](Self::with_options) makes it smaller.
+///
+/// The guarantee only covers appends. While readers follow the file, a
+/// writer must not shrink it, or they fault on the truncated pages, nor
+/// rewrite elements already published, through `as_mut_slice`, `set`,
+/// `replace_tail` or `truncate`, or readers may see them half written. In
+/// the same process this would also race with the slices handed out by
+/// [`as_slice`](Self::as_slice).
+///
+/// If the file is marked dirty with no writer holding it, the last writer
+/// crashed, and the reader ends at the length it last flushed until a new
+/// writer has opened the file and rolled it back.
+///
+/// [`FileMappedSlice`]: crate::FileMappedSlice
+/// [`FileMappedVector`]: crate::FileMappedVector
+pub struct FileMappedReader<T: Pod> {
+    mapping: Mapping,
+    // keeps the mapped file open
+    _file: File,
+    // after a crash, the synced length and the PID of the writer that crashed
+    crashed: Option<(usize, u32)>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> FileMappedReader<T> {
+    /// Follows `file`. Equivalent to [`with_type_tag`](Self::with_type_tag)
+    /// with a tag of 0.
+    pub fn new(file: &File) -> Result<Self> {
+        Self::with_type_tag(file, 0)
+    }
+
+    /// Follows `file`, checking that it holds `T` tagged `type_tag`.
+    ///
+    /// Version 0 files carry no element metadata, so only their magic is
+    /// checked.
+    pub fn with_type_tag(file: &File, type_tag: u64) -> Result<Self> {
+        Self::with_options(file, VectorOptions::new().type_tag(type_tag))
+    }
+
+    /// Follows `file` with the type tag, reservation and `noreserve` setting
+    /// of `opts`; the rest only apply to writers.
+    pub fn with_options(file: &File, opts: &VectorOptions) -> Result<Self> {
+        pod::assert_not_zero_sized::<T>();
+        // a description of our own, so probing for a writer cannot drop a
+        // lock held through `file`
+        let file = lock::reopen(file)?;
+
+        let fsize = file.metadata()?.len() as usize;
+        if fsize < HEADER_SIZE {
+            return Err(FmvError::TruncatedHeader);
+        }
+
+        let reservation = opts.reservation.max(fsize).next_multiple_of(page_size());
+        let flags = if opts.noreserve { MAP_NORESERVE } else { 0 };
+        let mapping = Mapping::new(file.as_raw_fd(), reservation, PROT_READ, flags)?;
+
+        let header = unsafe { &*(mapping.as_ptr() as *const FMVHeader) };
+        if header.magic != MAGIC {
+            return Err(FmvError::BadMagic);
+        }
+        if header.version.get() != 0 {
+            header.validate::<T>(opts.type_tag)?;
+        }
+
+        let crashed = if header.flags.get() & FLAG_DIRTY != 0 && !lock::has_writer(&file)? {
+            Some((header.synced_size(Acquire), header.writer_pid.get()))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            mapping,
+            _file: file,
+            crashed,
+            _marker: PhantomData,
+        })
+    }
+
+    fn header(&self) -> &FMVHeader {
+        unsafe { &*(self.mapping.as_ptr() as *const FMVHeader) }
+    }
+
+    /// Number of elements the writer has published so far.
+    ///
+    /// Capped at what the mapping covers; call [`refresh`](Self::refresh) if
+    /// the file may have outgrown it.
+    pub fn len(&self) -> usize {
+        let mapped = (self.mapping.len() - HEADER_SIZE) / size_of::<T>();
+        let size = match self.crashed {
+            Some((synced, _)) => self.header().size(Acquire).min(synced),
+            None => self.header().size(Acquire),
+        };
+        size.min(mapped)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remaps if the writer has grown the file past the reservation, and
+    /// returns the current length.
+    pub fn refresh(&mut self) -> Result<usize> {
+        // a new writer records its PID once it has rolled the file back
+        if let Some((_, pid)) = self.crashed {
+            if self.header().writer_pid.get() != pid {
+                self.crashed = None;
+            }
+        }
+
+        let size = self.header().size(Acquire);
+        let needed = HEADER_SIZE + size * size_of::<T>();
+        if needed > self.mapping.len() {
+            let len = needed.max(self.mapping.len() * 2);
+            self.mapping.remap(len.next_multiple_of(page_size()))?;
+        }
+
+        Ok(self.len())
+    }
+
+    /// Blocks until the writer has published at least `len` elements, or
+    /// `timeout` runs out. Returns the length seen last, which is below `len`
+    /// only on timeout.
+    pub fn wait_for_len(&mut self, len: usize, timeout: Option<Duration>) -> Result<usize> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut interval = Duration::from_micros(1);
+
+        loop {
+            let current = self.refresh()?;
+            if current >= len {
+                return Ok(current);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(current);
+            }
+
+            // the writer does not signal appends, so poll with backoff
+            std::thread::sleep(interval);
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+
+    /// Hints how the published elements in `range` are about to be
+    /// accessed.
+    ///
+    /// [`refresh`](Self::refresh) may remap the file and reset all hints.
+    pub fn advise(&self, range: Range<usize>, access: Access) -> Result<()> {
+        assert!(range.start <= range.end && range.end <= self.len());
+        self.mapping.advise_elements(range, size_of::<T>(), access)
+    }
+
+    /// The elements published so far, borrowed straight from the mapping.
+    ///
+    /// The slice is a snapshot: its length does not change as the writer
+    /// appends.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe {
+            let data = self.mapping.as_ptr().add(HEADER_SIZE) as *const T;
+            std::slice::from_raw_parts(data, self.len())
+        }
+    }
+}
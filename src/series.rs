@@ -0,0 +1,614 @@
+//! Time series stored as a pair of columns.
+
+use std::{
+    fs::File,
+    io,
+    ops::{Bound, Range, RangeBounds},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    access::Access,
+    aggregate::{self, Aggregated, Aggregation},
+    error::{FmvError, Result},
+    options::VectorOptions,
+    slice::FileMappedSlice,
+    vector::FileMappedVector,
+};
+
+/// File name of the timestamp column inside a series directory.
+pub const TIMESTAMPS_FILE: &str = "timestamps";
+/// File name of the value column inside a series directory.
+pub const VALUES_FILE: &str = "values";
+
+/// Directory inside a series directory holding a committed merge that has
+/// not been applied to the columns yet. It is laid out like a series
+/// directory itself.
+pub const MERGE_DIR: &str = "merge";
+// where a merge is written before it is committed
+const MERGE_TMP_DIR: &str = "merge.tmp";
+
+/// Type tag of the timestamp column, "tsdb:ts" in ASCII.
+pub const TIMESTAMPS_TAG: u64 = u64::from_le_bytes(*b"tsdb:ts\0");
+/// Type tag of the value column, "tsdb:val" in ASCII.
+pub const VALUES_TAG: u64 = u64::from_le_bytes(*b"tsdb:val");
+
+/// A time series of `(timestamp, value)` points.
+///
+/// Points are stored column by column in a directory: timestamps in a
+/// [`FileMappedVector<i64>`] and values in a [`FileMappedVector<f64>`], so a
+/// scan over one column never reads the other. Timestamps are opaque to the
+/// series; any unit works as long as it is used consistently.
+///
+/// Queries such as [`range`](Self::range) binary-search the timestamp
+/// column, so points are kept in timestamp order. What happens to a point
+/// older than the last one is up to the [`OutOfOrderPolicy`], and of one with
+/// the same timestamp as another to the [`DuplicatePolicy`].
+///
+/// # Crash consistency
+///
+/// The columns are separate files, so a crash can leave one of them ahead of
+/// the other, either mid-[`append`](Self::append) or because only one was
+/// synced. Each column recovers on its own as described for
+/// [`FileMappedVector`], and opening the series then truncates both to the
+/// shorter one, so a point is either wholly present or wholly gone.
+///
+/// A [`merge`](Self::merge) rewrites the columns in place, so it is written
+/// to [`MERGE_DIR`] and committed first. If a crash interrupts the rewrite,
+/// opening the series finishes it from there.
+///
+/// # Readers
+///
+/// A [`FileMappedReader`] can follow the columns of a series as they are
+/// appended to, but not through a merge, which may show it a column partly
+/// rewritten: out of order, or with points twice. Only follow series that
+/// reject or drop late points.
+///
+/// [`FileMappedReader`]: crate::FileMappedReader
+pub struct Series {
+    dir: PathBuf,
+    timestamps: FileMappedVector<i64>,
+    values: FileMappedVector<f64>,
+    // settings for the columns of a merge
+    merge_opts: VectorOptions,
+    // a committed merge may not have been applied yet
+    unapplied: bool,
+    out_of_order: OutOfOrderPolicy,
+    duplicates: DuplicatePolicy,
+    late_window: Option<i64>,
+    // late points waiting to be merged, in arrival order
+    staged: Vec<(i64, f64)>,
+    dropped: u64,
+}
+
+/// What [`Series::append`] and merges do with a point whose timestamp is
+/// already in the series.
+///
+/// Appends only compare against the last timestamp, which is O(1); a late
+/// point is checked against the others when it is staged or merged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Store every point, duplicates included.
+    #[default]
+    Allow,
+    /// Keep the point that arrived first, discarding later ones.
+    KeepFirst,
+    /// Keep the value of the point that arrived last.
+    KeepLast,
+    /// Fail with [`FmvError::DuplicateTimestamp`].
+    Error,
+}
+
+/// What [`Series::append`] does with a point whose timestamp is before the
+/// last one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutOfOrderPolicy {
+    /// Fail with [`FmvError::OutOfOrder`].
+    #[default]
+    Reject,
+    /// Discard the point, counting it in [`Series::dropped`].
+    Drop,
+    /// Keep the point in memory until this many are staged, or until
+    /// [`Series::merge`], [`Series::flush`] or drop, then merge them all into
+    /// the columns in timestamp order.
+    ///
+    /// Staged points are not visible to queries, and are lost in a crash.
+    Stage(usize),
+}
+
+impl Series {
+    /// Opens the series in directory `dir`, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        Self::with_options(dir, &VectorOptions::new())
+    }
+
+    /// Like [`open`](Self::open), but opening both columns with `opts`.
+    ///
+    /// The type tags of `opts` are replaced with [`TIMESTAMPS_TAG`] and
+    /// [`VALUES_TAG`].
+    pub fn with_options(dir: impl AsRef<Path>, opts: &VectorOptions) -> Result<Self> {
+        let dir = dir.as_ref();
+        if !dir.try_exists()? {
+            std::fs::create_dir_all(dir)?;
+            // the new directory must outlive a crash along with its columns
+            let parent = dir.parent().filter(|parent| !parent.as_os_str().is_empty());
+            sync_dir(parent.unwrap_or(Path::new(".")))?;
+        }
+        let (timestamps, values) = open_columns(dir, opts)?;
+        let mut merge_opts = VectorOptions::new();
+        merge_opts
+            .reservation(opts.reservation)
+            .noreserve(opts.noreserve);
+
+        let mut series = Self {
+            dir: dir.to_path_buf(),
+            timestamps,
+            values,
+            merge_opts,
+            unapplied: true,
+            out_of_order: OutOfOrderPolicy::default(),
+            duplicates: DuplicatePolicy::default(),
+            late_window: None,
+            staged: Vec::new(),
+            dropped: 0,
+        };
+
+        // drop a point only one of the columns got to
+        let len = series.timestamps.len().min(series.values.len());
+        series.timestamps.truncate(len);
+        series.values.truncate(len);
+
+        series.finish_merge()?;
+        Ok(series)
+    }
+
+    /// Appends a point.
+    ///
+    /// Points older than the last one are handled according to the
+    /// [`OutOfOrderPolicy`], and points with the same timestamp as the last
+    /// one according to the [`DuplicatePolicy`]. If appending to either
+    /// column fails the series is left unchanged.
+    pub fn append(&mut self, ts: i64, value: f64) -> Result<()> {
+        self.finish_merge()?;
+        match self.timestamps().last() {
+            Some(&last) if ts < last => return self.append_late(last, ts, value),
+            Some(&last) if ts == last => match self.duplicates {
+                DuplicatePolicy::Allow => {}
+                DuplicatePolicy::KeepFirst => {
+                    self.dropped += 1;
+                    return Ok(());
+                }
+                DuplicatePolicy::KeepLast => {
+                    self.dropped += 1;
+                    return self.values.replace_tail(self.len() - 1, &[value]);
+                }
+                DuplicatePolicy::Error => return Err(FmvError::DuplicateTimestamp(ts)),
+            },
+            _ => {}
+        }
+
+        let len = self.len();
+        let pushed = self
+            .timestamps
+            .push(ts)
+            .and_then(|()| self.values.push(value));
+        if pushed.is_err() {
+            // a push can fail after storing the element, e.g. on a sync, so
+            // cut both columns back rather than undoing one
+            self.timestamps.truncate(len);
+            self.values.truncate(len);
+        }
+        pushed
+    }
+
+    fn append_late(&mut self, last: i64, ts: i64, value: f64) -> Result<()> {
+        match self.out_of_order {
+            OutOfOrderPolicy::Reject => Err(FmvError::OutOfOrder { last, ts }),
+            OutOfOrderPolicy::Drop => {
+                self.dropped += 1;
+                Ok(())
+            }
+            OutOfOrderPolicy::Stage(limit) => {
+                if self
+                    .late_window
+                    .is_some_and(|window| ts < last.saturating_sub(window))
+                {
+                    return Err(FmvError::OutOfOrder { last, ts });
+                }
+                // refuse duplicates now rather than fail the merge later
+                if self.duplicates == DuplicatePolicy::Error
+                    && (self.timestamps().binary_search(&ts).is_ok()
+                        || self.staged.iter().any(|&(staged, _)| staged == ts))
+                {
+                    return Err(FmvError::DuplicateTimestamp(ts));
+                }
+
+                self.staged.push((ts, value));
+                if self.staged.len() >= limit {
+                    self.merge()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Merges the staged late points into the columns.
+    ///
+    /// Everything from the earliest staged timestamp on is rewritten in
+    /// timestamp order, twice: once into the merge and once into the
+    /// columns, so a single point far in the past rewrites every point since.
+    /// [`set_late_window`](Self::set_late_window) bounds this. Points with
+    /// equal timestamps keep the order they arrived in, then the
+    /// [`DuplicatePolicy`] picks which of them stay.
+    /// Under [`DuplicatePolicy::Error`], a staged point with the timestamp of
+    /// another fails the merge and stays staged; that happens if the policy
+    /// was switched after the point was staged.
+    ///
+    /// The rewritten points are first written to a series of their own,
+    /// which a rename into [`MERGE_DIR`] commits before the columns are
+    /// overwritten and flushed. The staged points are only let go of once
+    /// the merge is committed; a crash after that leaves the rest to the
+    /// next [`open`](Self::open).
+    pub fn merge(&mut self) -> Result<()> {
+        self.finish_merge()?;
+        if self.staged.is_empty() {
+            return Ok(());
+        }
+
+        // stable, so equal timestamps stay in arrival order
+        self.staged.sort_by_key(|&(ts, _)| ts);
+        // stored points equal to the earliest staged one arrived before it,
+        // so they are merged too, to be deduplicated
+        let from = self
+            .timestamps()
+            .partition_point(|&ts| ts < self.staged[0].0);
+
+        // left behind by a merge that failed before its commit
+        let tmp = self.dir.join(MERGE_TMP_DIR);
+        remove_dir_if_exists(&tmp)?;
+        std::fs::create_dir(&tmp)?;
+        let mut opts = self.merge_opts.clone();
+        opts.capacity(self.len() - from + self.staged.len());
+        let (timestamps, values) = open_columns(&tmp, &opts)?;
+        let mut merged = Merged {
+            timestamps,
+            values,
+            policy: self.duplicates,
+            discarded: 0,
+        };
+
+        let tail = self.timestamps()[from..]
+            .iter()
+            .copied()
+            .zip(self.values()[from..].iter().copied());
+        let mut staged = self.staged.iter().copied().peekable();
+        for (ts, value) in tail {
+            while let Some((late_ts, late_value)) = staged.next_if(|&(late, _)| late < ts) {
+                merged.push(late_ts, late_value, true)?;
+            }
+            merged.push(ts, value, false)?;
+        }
+        for (ts, value) in staged {
+            merged.push(ts, value, true)?;
+        }
+        merged.timestamps.flush()?;
+        merged.values.flush()?;
+        let discarded = merged.discarded;
+        drop(merged);
+
+        // `open_columns` synced the column entries into `tmp`
+        std::fs::rename(&tmp, self.dir.join(MERGE_DIR))?;
+        sync_dir(&self.dir)?;
+        self.staged.clear();
+        self.dropped += discarded;
+        self.unapplied = true;
+        self.finish_merge()
+    }
+
+    /// Applies the merge committed in [`MERGE_DIR`], if there is one, and
+    /// removes it.
+    fn finish_merge(&mut self) -> Result<()> {
+        if !self.unapplied {
+            return Ok(());
+        }
+
+        let committed = self.dir.join(MERGE_DIR);
+        if committed.try_exists()? {
+            let open = |name| File::open(committed.join(name));
+            let timestamps =
+                FileMappedSlice::with_type_tag(&open(TIMESTAMPS_FILE)?, TIMESTAMPS_TAG)?;
+            let values = FileMappedSlice::with_type_tag(&open(VALUES_FILE)?, VALUES_TAG)?;
+            self.apply(&timestamps, &values)?;
+            drop((timestamps, values));
+
+            // take the commit back before removing anything, so a crash
+            // cannot leave half a merge to be applied again
+            let tmp = self.dir.join(MERGE_TMP_DIR);
+            remove_dir_if_exists(&tmp)?;
+            std::fs::rename(&committed, &tmp)?;
+            sync_dir(&self.dir)?;
+            self.unapplied = false;
+            return remove_dir_if_exists(&tmp);
+        }
+
+        self.unapplied = false;
+        Ok(())
+    }
+
+    /// Overwrites the columns from the first of `timestamps` on with the
+    /// points of a merge, and flushes them.
+    ///
+    /// Applying the same merge again gives the same columns, as the stored
+    /// points before the merge are all older than its first.
+    fn apply(&mut self, timestamps: &[i64], values: &[f64]) -> Result<()> {
+        let Some(&first) = timestamps.first() else {
+            return Ok(());
+        };
+        let from = self.timestamps().partition_point(|&ts| ts < first);
+        let additional = (from + timestamps.len()).saturating_sub(self.len());
+
+        // grow both first, so neither column is rewritten unless both can
+        // be; after that only syncs can fail, with the data already in
+        // place, so both are rewritten regardless
+        self.timestamps.reserve(additional)?;
+        self.values.reserve(additional)?;
+        let rewritten = self.timestamps.replace_tail(from, timestamps);
+        rewritten.and(self.values.replace_tail(from, values))?;
+
+        self.timestamps.flush()?;
+        self.values.flush()
+    }
+
+    /// The policy for points older than the last one. Defaults to
+    /// [`OutOfOrderPolicy::Reject`].
+    pub fn out_of_order_policy(&self) -> OutOfOrderPolicy {
+        self.out_of_order
+    }
+
+    /// Switches the policy for points older than the last one. Points
+    /// already staged stay staged until the next merge.
+    pub fn set_out_of_order_policy(&mut self, policy: OutOfOrderPolicy) {
+        self.out_of_order = policy;
+    }
+
+    /// How far behind the last point a late point may be staged, in
+    /// timestamp units. Defaults to `None`, for no limit.
+    pub fn late_window(&self) -> Option<i64> {
+        self.late_window
+    }
+
+    /// Limits staging under [`OutOfOrderPolicy::Stage`] to points at most
+    /// `window` timestamp units older than the last point; older ones fail
+    /// with [`FmvError::OutOfOrder`].
+    ///
+    /// This bounds how much a merge rewrites, to about the points in the
+    /// window plus those appended before the merge. Points already staged
+    /// stay staged.
+    pub fn set_late_window(&mut self, window: Option<i64>) {
+        self.late_window = window;
+    }
+
+    /// The policy for points with a timestamp already in the series.
+    /// Defaults to [`DuplicatePolicy::Allow`].
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicates
+    }
+
+    /// Switches the policy for points with a timestamp already in the
+    /// series. Points stored before are only deduplicated if a merge
+    /// rewrites them; staged points are checked against the new policy when
+    /// they are merged.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates = policy;
+    }
+
+    /// Number of late points staged for the next merge.
+    pub fn staged(&self) -> usize {
+        self.staged.len()
+    }
+
+    /// Number of points discarded under [`OutOfOrderPolicy::Drop`],
+    /// [`DuplicatePolicy::KeepFirst`] or [`DuplicatePolicy::KeepLast`] since
+    /// the series was opened.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of points.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The timestamp column, borrowed straight from the mapping.
+    pub fn timestamps(&self) -> &[i64] {
+        self.timestamps.as_slice()
+    }
+
+    /// The value column, borrowed straight from the mapping.
+    pub fn values(&self) -> &[f64] {
+        self.values.as_slice()
+    }
+
+    /// The points with timestamps in `range`, borrowed straight from the
+    /// mappings.
+    ///
+    /// Both ends are found by binary search, and the selected part of each
+    /// column is prefetched with [`Access::WillNeed`].
+    pub fn range(&self, range: impl RangeBounds<i64>) -> Points<'_> {
+        let indices = self.indices(range);
+        if !indices.is_empty() {
+            // only a hint, so a failure is not worth reporting
+            let _ = self.timestamps.advise(indices.clone(), Access::WillNeed);
+            let _ = self.values.advise(indices.clone(), Access::WillNeed);
+        }
+
+        Points {
+            timestamps: &self.timestamps()[indices.clone()],
+            values: &self.values()[indices],
+        }
+    }
+
+    /// Indices of the points with timestamps in `range`.
+    fn indices(&self, range: impl RangeBounds<i64>) -> Range<usize> {
+        let timestamps = self.timestamps();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => timestamps.partition_point(|&ts| ts < start),
+            Bound::Excluded(&start) => timestamps.partition_point(|&ts| ts <= start),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => timestamps.partition_point(|&ts| ts <= end),
+            Bound::Excluded(&end) => timestamps.partition_point(|&ts| ts < end),
+            Bound::Unbounded => timestamps.len(),
+        };
+        start..end.max(start)
+    }
+
+    /// The first point with a timestamp after `ts`.
+    pub fn first_after(&self, ts: i64) -> Option<(i64, f64)> {
+        let i = self.timestamps().partition_point(|&t| t <= ts);
+        self.get(i)
+    }
+
+    /// The last point with a timestamp before `ts`.
+    pub fn last_before(&self, ts: i64) -> Option<(i64, f64)> {
+        let i = self.timestamps().partition_point(|&t| t < ts);
+        self.get(i.checked_sub(1)?)
+    }
+
+    /// Aggregates the points in `range` over windows `width` timestamp units
+    /// wide, as described for [`aggregate`](crate::aggregate::aggregate).
+    ///
+    /// # Panics
+    ///
+    /// If `width` is not positive.
+    pub fn aggregate(
+        &self,
+        range: impl RangeBounds<i64>,
+        width: i64,
+        aggregation: Aggregation,
+    ) -> Aggregated {
+        aggregate::aggregate(self.range(range), width, aggregation)
+    }
+
+    /// The point at index `i`.
+    pub fn get(&self, i: usize) -> Option<(i64, f64)> {
+        Some((*self.timestamps().get(i)?, *self.values().get(i)?))
+    }
+
+    /// Merges any staged points, then makes every point appended so far
+    /// durable.
+    pub fn flush(&mut self) -> Result<()> {
+        self.merge()?;
+        self.timestamps.flush()?;
+        self.values.flush()
+    }
+}
+
+/// Opens or creates the columns of the series in `dir`, and syncs `dir` so
+/// that columns just created survive a crash.
+fn open_columns(
+    dir: &Path,
+    opts: &VectorOptions,
+) -> Result<(FileMappedVector<i64>, FileMappedVector<f64>)> {
+    let open = |name| {
+        File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(name))
+    };
+    let timestamps = opts
+        .clone()
+        .type_tag(TIMESTAMPS_TAG)
+        .open(open(TIMESTAMPS_FILE)?)?;
+    let values = opts.clone().type_tag(VALUES_TAG).open(open(VALUES_FILE)?)?;
+    sync_dir(dir)?;
+    Ok((timestamps, values))
+}
+
+/// Makes the files created in or renamed into `dir` durable.
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+fn remove_dir_if_exists(dir: &Path) -> Result<()> {
+    match std::fs::remove_dir_all(dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// The columns of a merge being written, deduplicated as points are pushed.
+struct Merged {
+    timestamps: FileMappedVector<i64>,
+    values: FileMappedVector<f64>,
+    policy: DuplicatePolicy,
+    discarded: u64,
+}
+
+impl Merged {
+    /// Pushes a point, `late` if it was staged. Stored points go first among
+    /// equal timestamps, so a late one is never followed by its duplicate.
+    fn push(&mut self, ts: i64, value: f64, late: bool) -> Result<()> {
+        if self.timestamps.last() == Some(&ts) {
+            match self.policy {
+                DuplicatePolicy::KeepFirst => {
+                    self.discarded += 1;
+                    return Ok(());
+                }
+                DuplicatePolicy::KeepLast => {
+                    self.values.set(self.values.len() - 1, value);
+                    self.discarded += 1;
+                    return Ok(());
+                }
+                DuplicatePolicy::Error if late => {
+                    return Err(FmvError::DuplicateTimestamp(ts));
+                }
+                // duplicates among stored points predate the policy
+                DuplicatePolicy::Allow | DuplicatePolicy::Error => {}
+            }
+        }
+
+        self.timestamps.push(ts)?;
+        self.values.push(value)
+    }
+}
+
+impl Drop for Series {
+    fn drop(&mut self) {
+        // the columns sync themselves as they drop
+        let _ = self.merge();
+    }
+}
+
+/// Points of a [`Series`], borrowed column by column.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Points<'a> {
+    pub timestamps: &'a [i64],
+    pub values: &'a [f64],
+}
+
+impl<'a> Points<'a> {
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// The points as `(timestamp, value)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, f64)> + 'a {
+        self.timestamps
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+}
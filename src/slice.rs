@@ -0,0 +1,186 @@
+//! Read-only view of a vector file.
+
+use std::{
+    fs::File,
+    marker::PhantomData,
+    ops::{Deref, Range},
+    os::fd::AsRawFd,
+    sync::atomic::Ordering,
+};
+
+use libc::{MADV_WILLNEED, PROT_READ};
+
+use crate::{
+    access::Access,
+    checksum,
+    error::{FmvError, Result},
+    header::{FMVHeader, FLAG_DIRTY, HEADER_SIZE, MAGIC},
+    lock::{self, LockMode},
+    mapping::Mapping,
+    pod::{self, Pod},
+};
+
+/// A read-only mapping of a file written by a [`FileMappedVector`].
+///
+/// The file is mapped `PROT_READ` and never grown, so it may be opened
+/// read-only or live on a read-only mount. A shared `flock` is held while the
+/// slice lives, so no writer can change the file under it.
+///
+/// If the last writer did not shut down cleanly, the slice ends at the length
+/// it last flushed, like a [`FileMappedVector`] reopening the file would.
+///
+/// [`FileMappedVector`]: crate::FileMappedVector
+pub struct FileMappedSlice<T: Pod> {
+    len: usize,
+    mapping: Mapping,
+    // an open file description of our own, whose lock goes with it
+    _file: File,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> FileMappedSlice<T> {
+    /// Maps `file` read-only. Equivalent to
+    /// [`with_type_tag`](Self::with_type_tag) with a tag of 0.
+    pub fn new(file: &File) -> Result<Self> {
+        Self::with_type_tag(file, 0)
+    }
+
+    /// Maps `file` read-only, checking that it holds `T` tagged `type_tag`.
+    ///
+    /// Waits for a writer holding the file to drop it first. Version 0 files
+    /// carry no element metadata, so only their magic is checked.
+    pub fn with_type_tag(file: &File, type_tag: u64) -> Result<Self> {
+        Self::open(file, type_tag, true)
+    }
+
+    /// Like [`new`](Self::new), but fails with [`FmvError::Locked`] instead of
+    /// waiting if a writer holds the file.
+    pub fn try_new(file: &File) -> Result<Self> {
+        Self::open(file, 0, false)
+    }
+
+    /// Like [`with_type_tag`](Self::with_type_tag), but fails with
+    /// [`FmvError::Locked`] instead of waiting if a writer holds the file.
+    pub fn try_with_type_tag(file: &File, type_tag: u64) -> Result<Self> {
+        Self::open(file, type_tag, false)
+    }
+
+    fn open(file: &File, type_tag: u64, blocking: bool) -> Result<Self> {
+        pod::assert_not_zero_sized::<T>();
+        // a clone would share the caller's description, and with it the
+        // lock of every other slice opened from the same `File`
+        let file = lock::reopen(file)?;
+        lock::lock(&file, LockMode::Shared, blocking)?;
+        let (len, mapping) = Self::map(&file, type_tag)?;
+
+        Ok(Self {
+            len,
+            mapping,
+            _file: file,
+            _marker: PhantomData,
+        })
+    }
+
+    fn map(file: &File, type_tag: u64) -> Result<(usize, Mapping)> {
+        let fsize = file.metadata()?.len() as usize;
+        if fsize < HEADER_SIZE {
+            return Err(FmvError::TruncatedHeader);
+        }
+
+        // the file cannot grow under us, so only map what is there
+        let mapping = Mapping::new(file.as_raw_fd(), fsize, PROT_READ, 0)?;
+
+        let header = unsafe { &*(mapping.as_ptr() as *const FMVHeader) };
+        if header.magic != MAGIC {
+            return Err(FmvError::BadMagic);
+        }
+        if header.version.get() != 0 {
+            header.validate::<T>(type_tag)?;
+        }
+
+        let data_size = fsize - HEADER_SIZE;
+        if !data_size.is_multiple_of(size_of::<T>()) {
+            return Err(FmvError::SizeNotMultipleOfElement);
+        }
+
+        // the shared lock keeps writers out, so a dirty mark was left by one
+        // that crashed
+        let len = if header.flags.get() & FLAG_DIRTY != 0 {
+            header.synced_size(Ordering::Acquire)
+        } else {
+            header.size(Ordering::Acquire)
+        };
+        if len > data_size / size_of::<T>() {
+            return Err(FmvError::SizeExceedsFile);
+        }
+
+        mapping.advise(HEADER_SIZE, len * size_of::<T>(), MADV_WILLNEED);
+
+        Ok((len, mapping))
+    }
+
+    fn data(&self) -> *const T {
+        unsafe { self.mapping.as_ptr().add(HEADER_SIZE) as *const T }
+    }
+
+    /// Number of elements in the file when it was opened.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The elements, borrowed straight from the mapping.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.data(), self.len) }
+    }
+
+    /// Hints how the elements in `range` are about to be accessed, e.g.
+    /// [`Access::Sequential`] before a scan and [`Access::DontNeed`] after.
+    pub fn advise(&self, range: Range<usize>, access: Access) -> Result<()> {
+        assert!(range.start <= range.end && range.end <= self.len);
+        self.mapping.advise_elements(range, size_of::<T>(), access)
+    }
+
+    /// Checks the elements against the CRC32C checksums in `sidecar`, as
+    /// written by [`FileMappedVector::enable_checksums`], and returns the
+    /// element ranges of corrupt blocks.
+    ///
+    /// [`FileMappedVector::enable_checksums`]: crate::FileMappedVector::enable_checksums
+    pub fn verify(&self, sidecar: &File) -> Result<Vec<Range<usize>>> {
+        let data = unsafe {
+            std::slice::from_raw_parts(self.data() as *const u8, self.len * size_of::<T>())
+        };
+
+        let corrupt = checksum::verify(sidecar, data)?;
+        Ok(corrupt
+            .into_iter()
+            .map(|bytes| checksum::element_range(bytes, size_of::<T>()))
+            .collect())
+    }
+}
+
+impl<T: Pod> Deref for FileMappedSlice<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Pod> AsRef<[T]> for FileMappedSlice<T> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<'a, T: Pod> IntoIterator for &'a FileMappedSlice<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
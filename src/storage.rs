@@ -0,0 +1,36 @@
+//! The append interface shared by the vector backends.
+
+use crate::{error::Result, pod::Pod};
+
+/// Append-only storage of elements in the vector file format.
+///
+/// [`FileMappedVector`] writes through a shared mapping and leaves writeback
+/// to the page cache; [`BufferedVector`] collects appends in a buffer and
+/// writes it out with `pwrite`. Both produce the same files, so either can
+/// reopen what the other wrote, and [`FileMappedSlice`] and
+/// [`FileMappedReader`] read files from both.
+///
+/// [`FileMappedVector`]: crate::FileMappedVector
+/// [`BufferedVector`]: crate::BufferedVector
+/// [`FileMappedSlice`]: crate::FileMappedSlice
+/// [`FileMappedReader`]: crate::FileMappedReader
+pub trait Storage {
+    type Item: Pod;
+
+    /// Number of elements appended so far, including any not yet written
+    /// out.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends one element.
+    fn push(&mut self, value: Self::Item) -> Result<()>;
+
+    /// Appends all of `values`.
+    fn extend_from_slice(&mut self, values: &[Self::Item]) -> Result<()>;
+
+    /// Makes every element appended so far durable.
+    fn flush(&mut self) -> Result<()>;
+}
@@ -0,0 +1,173 @@
+//! When appended data is made durable.
+
+use std::{
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use libc::MS_SYNC;
+
+use crate::{
+    error::{FmvError, Result},
+    header::{FMVHeader, HEADER_SIZE},
+    mapping,
+};
+
+/// How eagerly a [`FileMappedVector`] syncs appended elements to disk.
+///
+/// Every policy other than `Never` goes through the same commit as
+/// [`FileMappedVector::flush`], so after a crash the vector reopens at the
+/// length of the last completed sync. The policy only decides how much can be
+/// lost and who pays for the `msync`.
+///
+/// [`FileMappedVector`]: crate::FileMappedVector
+/// [`FileMappedVector::flush`]: crate::FileMappedVector::flush
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Never sync. The length is published as elements are pushed and the
+    /// data reaches the disk whenever the kernel writes it back, so a power
+    /// loss may leave garbage below the published length.
+    Never,
+    /// Sync only when the vector is dropped or flushed explicitly.
+    #[default]
+    OnDrop,
+    /// Sync on the appending thread once this many elements have been
+    /// appended since the last sync.
+    EveryN(usize),
+    /// Sync from a background thread at this interval. Appends never block
+    /// on the disk.
+    EveryInterval(Duration),
+    /// Sync on the appending thread after every append call.
+    Always,
+}
+
+/// State shared between a vector and its background flusher.
+pub(crate) struct Shared {
+    // address of the mapping, header first; moves if the mapping is remapped
+    base: AtomicUsize,
+    elem_size: usize,
+
+    // held while the synced length is published or lowered
+    publish: Mutex<()>,
+
+    stop: Mutex<bool>,
+    wake: Condvar,
+    // first error hit by the flusher, reported by the next explicit flush
+    error: Mutex<Option<FmvError>>,
+}
+
+impl Shared {
+    pub fn new(base: *mut u8, elem_size: usize) -> Self {
+        Self {
+            base: AtomicUsize::new(base as usize),
+            elem_size,
+            publish: Mutex::new(()),
+            stop: Mutex::new(false),
+            wake: Condvar::new(),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Points the flusher at a remapped region. Call with the publish lock
+    /// held.
+    pub fn set_base(&self, base: *mut u8) {
+        self.base.store(base as usize, Relaxed);
+    }
+
+    /// Lock to hold while lowering the synced length or moving the mapping.
+    pub fn publish_lock(&self) -> std::sync::MutexGuard<'_, ()> {
+        self.publish.lock().unwrap()
+    }
+
+    /// Syncs the elements from `min(synced_size, from)` up to the current
+    /// length, then publishes that length as synced.
+    pub fn commit(&self, from: usize) -> Result<()> {
+        let _guard = self.publish_lock();
+        let base = self.base.load(Relaxed) as *mut u8;
+        let header = unsafe { &*(base as *const FMVHeader) };
+
+        let size = header.size(Acquire);
+        let from = header.synced_size(Acquire).min(from);
+
+        // msync rather than sync_file_range: the new blocks were fallocated,
+        // and only a real sync persists the extent metadata along with them
+        if from < size {
+            unsafe {
+                mapping::sync_range(
+                    base,
+                    HEADER_SIZE + from * self.elem_size,
+                    (size - from) * self.elem_size,
+                    MS_SYNC,
+                )?
+            };
+        }
+
+        header.set_synced_size(size, Release);
+        unsafe { mapping::sync_range(base, 0, HEADER_SIZE, MS_SYNC) }
+    }
+
+    /// Keeps `err` for the next explicit flush to report, unless an earlier
+    /// error is already waiting.
+    pub fn report(&self, err: FmvError) {
+        self.error.lock().unwrap().get_or_insert(err);
+    }
+
+    /// Takes the error left behind by the background flusher, if any.
+    pub fn take_error(&self) -> Option<FmvError> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+/// Background thread that commits a vector every `interval`.
+pub(crate) struct Flusher {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Flusher {
+    pub fn spawn(shared: Arc<Shared>, interval: Duration) -> Self {
+        *shared.stop.lock().unwrap() = false;
+
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || loop {
+                {
+                    let stop = shared.stop.lock().unwrap();
+                    let (stop, _) = shared
+                        .wake
+                        .wait_timeout_while(stop, interval, |stop| !*stop)
+                        .unwrap();
+                    if *stop {
+                        return;
+                    }
+                }
+
+                if let Err(err) = shared.commit(usize::MAX) {
+                    shared.report(err);
+                }
+            })
+        };
+
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        *self.shared.stop.lock().unwrap() = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
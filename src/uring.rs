@@ -0,0 +1,272 @@
+//! The io_uring engine behind [`BufferedOptions::io_uring`].
+//!
+//! [`BufferedOptions::io_uring`]: crate::BufferedOptions::io_uring
+
+use std::{fs::File, io, ops::Range, os::fd::AsRawFd};
+
+use io_uring::{opcode, squeue::Flags, types, IoUring, Probe};
+use libc::iovec;
+
+use crate::{
+    buffered::AlignedBuf,
+    error::{FmvError, Result},
+};
+
+/// Number of append buffers, and so of buffer writes in flight at once.
+pub(crate) const RING_BUFFERS: usize = 4;
+
+// user data of the entries in a sync chain; buffer writes use the index
+const CHAIN: u64 = u64::MAX;
+
+// the file is registered as fixed file 0
+const FILE: types::Fixed = types::Fixed(0);
+
+/// An io_uring with a set of registered buffers that are filled in turn and
+/// written out without waiting.
+pub(crate) struct Ring {
+    // torn down before the buffers it has registered
+    ring: IoUring,
+    buffers: Vec<AlignedBuf>,
+    // bytes being written from each buffer, 0 if it is free
+    in_flight: Vec<usize>,
+    current: usize,
+    // entries of a sync chain not yet completed, and the buffer it writes
+    chain: usize,
+    chain_buffer: usize,
+    // direct writes rewrite the last partial block, so must not overlap
+    direct: bool,
+    // first failure seen while reaping, reported by the next call
+    error: Option<FmvError>,
+}
+
+impl Ring {
+    /// Sets up a ring writing to `file` through [`RING_BUFFERS`] registered
+    /// buffers of `buffer_size` bytes.
+    ///
+    /// Fails if the kernel lacks io_uring or any operation used here, or it
+    /// is disabled, as it often is in containers.
+    pub fn new(file: &File, buffer_size: usize, direct: bool) -> io::Result<Self> {
+        let ring = IoUring::new((RING_BUFFERS as u32 + 4).next_power_of_two())?;
+
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        let ops = [
+            opcode::WriteFixed::CODE,
+            opcode::Write::CODE,
+            opcode::Fsync::CODE,
+        ];
+        if !ops.iter().all(|&op| probe.is_supported(op)) {
+            return Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP));
+        }
+
+        let buffers: Vec<_> = (0..RING_BUFFERS)
+            .map(|_| AlignedBuf::zeroed(buffer_size))
+            .collect();
+        let iovecs: Vec<_> = buffers
+            .iter()
+            .map(|buf| iovec {
+                iov_base: buf.as_ptr() as *mut _,
+                iov_len: buf.len(),
+            })
+            .collect();
+        // the buffers are heap allocations owned by the ring, so they stay
+        // put for as long as they are registered
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+        ring.submitter().register_files(&[file.as_raw_fd()])?;
+
+        Ok(Self {
+            ring,
+            buffers,
+            in_flight: vec![0; RING_BUFFERS],
+            current: 0,
+            chain: 0,
+            chain_buffer: 0,
+            direct,
+            error: None,
+        })
+    }
+
+    /// The buffer being filled.
+    pub fn current(&mut self) -> &mut AlignedBuf {
+        &mut self.buffers[self.current]
+    }
+
+    /// Submits a write of the first `len` bytes of the current buffer at
+    /// `offset` and moves on to the next buffer, copying the bytes in `keep`
+    /// over to its start.
+    ///
+    /// Returns without waiting for the write, unless the next buffer is
+    /// still being written. Failures of earlier writes are reported before
+    /// anything is submitted; those seen while waiting are left for the next
+    /// call.
+    pub fn submit(&mut self, len: usize, offset: usize, keep: Range<usize>) -> Result<()> {
+        if self.direct {
+            self.wait(|ring| ring.chain == 0)?;
+        }
+        self.take_error()?;
+
+        let index = self.current;
+        let entry = self.write(index, len, offset).user_data(index as u64);
+        self.push(&[entry])?;
+        self.in_flight[index] = len;
+
+        self.rotate(keep)
+    }
+
+    /// Makes the next buffer current once it is free, copying the bytes in
+    /// `keep` of the old one to its start.
+    fn rotate(&mut self, keep: Range<usize>) -> Result<()> {
+        let index = self.current;
+        let next = (index + 1) % RING_BUFFERS;
+        self.wait(|ring| ring.is_free(next))?;
+
+        let (before, after) = self.buffers.split_at_mut(index.max(next));
+        let (from, to) = if index < next {
+            (&before[index], &mut after[0])
+        } else {
+            (&after[0], &mut before[next])
+        };
+        to[..keep.len()].copy_from_slice(&from[keep]);
+
+        self.current = next;
+        Ok(())
+    }
+
+    fn is_free(&self, index: usize) -> bool {
+        self.in_flight[index] == 0 && (self.chain == 0 || self.chain_buffer != index)
+    }
+
+    /// Waits for the buffer writes in flight, then submits a linked chain
+    /// that writes the first `len` bytes of the current buffer at `offset`,
+    /// syncs the data, writes `header` and syncs again.
+    ///
+    /// Each link only runs if the one before succeeded, so the header never
+    /// lands without the data it covers. Then moves on to the next buffer
+    /// like [`submit`](Self::submit), without waiting for the chain.
+    ///
+    /// # Safety
+    ///
+    /// `header` must stay alive and unchanged until the chain completes,
+    /// which the next `sync` or [`wait_all`](Self::wait_all) waits for.
+    pub unsafe fn sync(
+        &mut self,
+        len: usize,
+        offset: usize,
+        keep: Range<usize>,
+        header: &AlignedBuf,
+    ) -> Result<()> {
+        self.wait(|ring| ring.chain == 0 && ring.in_flight.iter().all(|&len| len == 0))?;
+        self.take_error()?;
+
+        let datasync = || {
+            opcode::Fsync::new(FILE)
+                .flags(types::FsyncFlags::DATASYNC)
+                .build()
+        };
+        let header_write = opcode::Write::new(FILE, header.as_ptr(), header.len() as u32)
+            .offset(0)
+            .build();
+
+        let mut chain = Vec::with_capacity(4);
+        if len != 0 {
+            chain.push(self.write(self.current, len, offset));
+        }
+        chain.push(datasync());
+        chain.push(header_write);
+        chain.push(datasync());
+
+        let last = chain.len() - 1;
+        for entry in &mut chain[..last] {
+            *entry = entry.clone().flags(Flags::IO_LINK);
+        }
+        let chain: Vec<_> = chain
+            .into_iter()
+            .map(|entry| entry.user_data(CHAIN))
+            .collect();
+
+        self.push(&chain)?;
+        self.chain = chain.len();
+        self.chain_buffer = self.current;
+        self.rotate(keep)
+    }
+
+    /// Waits for the last sync chain, so its header buffer can be reused.
+    pub fn wait_sync(&mut self) -> Result<()> {
+        self.wait(|ring| ring.chain == 0)?;
+        self.take_error()
+    }
+
+    /// Waits for every write and sync in flight.
+    pub fn wait_all(&mut self) -> Result<()> {
+        self.wait(|ring| ring.chain == 0 && ring.in_flight.iter().all(|&len| len == 0))?;
+        self.take_error()
+    }
+
+    fn write(&self, index: usize, len: usize, offset: usize) -> io_uring::squeue::Entry {
+        let buf = &self.buffers[index];
+        opcode::WriteFixed::new(FILE, buf.as_ptr(), len as u32, index as u16)
+            .offset(offset as u64)
+            .build()
+    }
+
+    fn push(&mut self, entries: &[io_uring::squeue::Entry]) -> Result<()> {
+        // at most one write per buffer and one chain are in flight, which
+        // the queue is sized for
+        if unsafe { self.ring.submission().push_multiple(entries) }.is_err() {
+            return Err(io::Error::other("io_uring submission queue full").into());
+        }
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    /// Reaps completions until `done` holds.
+    fn wait(&mut self, done: impl Fn(&Self) -> bool) -> Result<()> {
+        while !done(self) {
+            match self.ring.submit_and_wait(1) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            };
+
+            let completions: Vec<_> = self
+                .ring
+                .completion()
+                .map(|cqe| (cqe.user_data(), cqe.result()))
+                .collect();
+            for (user_data, result) in completions {
+                let expected = if user_data == CHAIN {
+                    self.chain -= 1;
+                    None
+                } else {
+                    let len = std::mem::take(&mut self.in_flight[user_data as usize]);
+                    Some(len)
+                };
+
+                let error = if result < 0 {
+                    Some(io::Error::from_raw_os_error(-result))
+                } else if expected.is_some_and(|len| result as usize != len) {
+                    Some(io::ErrorKind::WriteZero.into())
+                } else {
+                    None
+                };
+                if let Some(error) = error {
+                    self.error.get_or_insert(error.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn take_error(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // the kernel may still be reading the buffers
+        let _ = self.wait_all();
+    }
+}
@@ -0,0 +1,861 @@
+//! The file-mapped vector itself.
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    ops::{Deref, DerefMut, Range},
+    os::fd::AsRawFd,
+    sync::{
+        atomic::Ordering::{Relaxed, Release},
+        Arc,
+    },
+};
+
+use libc::{
+    fallocate, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE, MADV_DONTNEED, MADV_HUGEPAGE,
+    MADV_SEQUENTIAL, MADV_WILLNEED, MAP_NORESERVE, MAP_POPULATE, MS_SYNC, PROT_READ, PROT_WRITE,
+};
+
+use crate::{
+    access::Access,
+    checksum::{self, Checksums, DEFAULT_BLOCK_SIZE},
+    error::{FmvError, Result},
+    growth::GrowthPolicy,
+    header::{FMVHeader, FLAG_DIRTY, HEADER_SIZE, MAGIC},
+    lock::{self, LockMode},
+    mapping::{page_size, Mapping},
+    options::VectorOptions,
+    pod::{self, Pod},
+    storage::Storage,
+    sync::{Flusher, Shared, SyncPolicy},
+};
+
+fn round_up_to_page(len: usize) -> usize {
+    len.next_multiple_of(page_size())
+}
+
+/// An append-only vector of `T` stored in a memory-mapped file.
+///
+/// Elements are written straight into the shared mapping, so they reach the
+/// file through the page cache. Whenever the vector fills up the file is
+/// grown with `fallocate`, by default doubling its capacity; see
+/// [`GrowthPolicy`](crate::GrowthPolicy) for alternatives.
+///
+/// # Durability
+///
+/// The header keeps two lengths: `size`, bumped on every push, and
+/// `synced_size`, only advanced by [`flush`](Self::flush) once the elements
+/// below it have been `msync`ed. The kernel may write the header page back
+/// before or after any data page, so only `synced_size` means anything after
+/// a crash.
+///
+/// While a writer has the file open the header is marked dirty. Dropping the
+/// vector flushes and clears the mark. If [`new`](Self::new) finds the mark
+/// still set, the previous writer never shut down cleanly, and the length is
+/// rolled back to `synced_size`: elements pushed after the last flush are
+/// discarded, since nothing guarantees they reached the disk.
+///
+/// [`set_sync_policy`](Self::set_sync_policy) trades append latency against
+/// how much can be lost this way.
+///
+/// # Locking
+///
+/// A vector holds an exclusive `flock` on its file for as long as it lives,
+/// so opening the same file twice for writing waits for the first vector to
+/// be dropped, or fails with [`FmvError::Locked`] through
+/// [`try_new`](Self::try_new). The lock is taken on a descriptor of the
+/// vector's own, so this holds for handles cloned from one another too.
+/// Read-only [`FileMappedSlice`]s take a shared lock.
+///
+/// `T` is stored as raw bytes. The header records its size and alignment, so
+/// opening a file with an element type of another layout fails with
+/// [`FmvError::TypeMismatch`]; tell apart types of the same layout with a
+/// [`type_tag`](Self::with_type_tag).
+///
+/// [`FileMappedSlice`]: crate::FileMappedSlice
+pub struct FileMappedVector<T: Pod> {
+    file: File,
+    capacity: usize,
+
+    // `header` and `data` point into `mapping`, so it must outlive them
+    header: *mut FMVHeader,
+    data: *mut T,
+    mapping: Mapping,
+
+    // lowest element index rewritten or truncated away since the last flush
+    dirty_from: usize,
+
+    growth: GrowthPolicy,
+    policy: SyncPolicy,
+    // elements appended since the last flush, for `SyncPolicy::EveryN`
+    unsynced: usize,
+    shared: Arc<Shared>,
+    // dropped before `mapping`, which it reads from
+    flusher: Option<Flusher>,
+    checksums: Option<Checksums>,
+    // advised again after every remap, whose advice reset may not keep it
+    huge_pages: bool,
+    lock_tail: usize,
+    // bytes of the mapping currently mlocked
+    locked: Range<usize>,
+}
+
+impl<T: Pod> FileMappedVector<T> {
+    /// Maps `file` as a vector, initializing it if it is empty.
+    ///
+    /// `file` must be opened for both reading and writing; use
+    /// [`FileMappedSlice`](crate::FileMappedSlice) for read-only files. See
+    /// [`options`](Self::options) for non-default settings.
+    pub fn new(file: File) -> Result<Self> {
+        Self::options().open(file)
+    }
+
+    /// Maps `file` as a vector whose header is tagged with `type_tag`.
+    ///
+    /// New files record the tag along with the size and alignment of `T`;
+    /// existing files are rejected with [`FmvError::TypeMismatch`] unless all
+    /// three match.
+    pub fn with_type_tag(file: File, type_tag: u64) -> Result<Self> {
+        Self::options().type_tag(type_tag).open(file)
+    }
+
+    /// Maps `file` as a vector with room for at least `capacity` elements.
+    ///
+    /// New files are created at that capacity and existing ones grown to it.
+    pub fn with_capacity(file: File, capacity: usize) -> Result<Self> {
+        Self::options().capacity(capacity).open(file)
+    }
+
+    /// Options for opening a vector with non-default settings.
+    pub fn options() -> VectorOptions {
+        VectorOptions::new()
+    }
+
+    /// Like [`new`](Self::new), but fails with [`FmvError::Locked`] instead
+    /// of waiting if another process has the file open.
+    pub fn try_new(file: File) -> Result<Self> {
+        Self::options().try_open(file)
+    }
+
+    pub(crate) fn open_with(file: File, opts: &VectorOptions, blocking: bool) -> Result<Self> {
+        pod::assert_not_zero_sized::<T>();
+        let type_tag = opts.type_tag;
+
+        if file.metadata()?.permissions().readonly() {
+            return Err(FmvError::ReadOnly);
+        }
+
+        // the lock must not be shared with clones of `file` the caller kept,
+        // and is taken before anything else looks at the file, so two
+        // writers cannot both initialize it
+        let mut file = lock::reopen_writable(&file)?;
+        lock::lock(&file, LockMode::Exclusive, blocking)?;
+
+        let init_cap = size_of::<T>() * opts.growth_policy.initial_capacity(opts.capacity);
+        let initial_file_size = HEADER_SIZE + init_cap;
+
+        // open file, initialize if new file
+        let fsize = file.metadata()?.len();
+        if fsize == 0 {
+            file.set_len(initial_file_size as u64)?;
+
+            // write header to file
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(FMVHeader::new::<T>(type_tag).as_bytes())?;
+            file.seek(SeekFrom::Start(0))?;
+        }
+
+        // do some checks
+        let fsize = file.metadata()?.len() as usize;
+        if fsize < HEADER_SIZE {
+            return Err(FmvError::TruncatedHeader);
+        }
+
+        // mmap file
+        let reservation = round_up_to_page(opts.reservation.max(fsize));
+        let mut flags = if opts.noreserve { MAP_NORESERVE } else { 0 };
+        if opts.populate {
+            flags |= MAP_POPULATE;
+        }
+        let mapping = Mapping::new(file.as_raw_fd(), reservation, PROT_READ | PROT_WRITE, flags)?;
+
+        let header = mapping.as_ptr() as *mut FMVHeader;
+        let data = unsafe { mapping.as_ptr().add(HEADER_SIZE) } as *mut T;
+
+        // check a copy, so a file that fails to open is left as it was
+        let mut hdr = unsafe { header.read() };
+        let capacity = hdr.open_for_writing::<T>(type_tag, fsize)?;
+        unsafe { header.write(hdr) };
+        mapping.sync_range(0, HEADER_SIZE, MS_SYNC)?;
+
+        // madvise
+        let data_size = capacity * size_of::<T>();
+        mapping.advise(0, HEADER_SIZE, MADV_WILLNEED);
+        mapping.advise(HEADER_SIZE, data_size, MADV_WILLNEED);
+        mapping.advise(
+            HEADER_SIZE + data_size,
+            reservation - HEADER_SIZE - data_size,
+            MADV_DONTNEED,
+        );
+        if opts.huge_pages {
+            mapping.advise(0, reservation, MADV_HUGEPAGE);
+        }
+
+        let shared = Arc::new(Shared::new(mapping.as_ptr(), size_of::<T>()));
+
+        let mut vec = Self {
+            file,
+            capacity,
+            header,
+            data,
+            mapping,
+            dirty_from: usize::MAX,
+            growth: opts.growth_policy,
+            policy: SyncPolicy::default(),
+            unsynced: 0,
+            shared,
+            flusher: None,
+            checksums: None,
+            huge_pages: opts.huge_pages,
+            lock_tail: opts.lock_tail,
+            locked: 0..0,
+        };
+        vec.set_sync_policy(opts.sync_policy);
+        if let Some(capacity) = opts.capacity {
+            vec.grow(capacity)?;
+        }
+        if vec.lock_tail != 0 {
+            vec.relock_tail()?;
+        }
+        Ok(vec)
+    }
+
+    /// Appends `value`, growing the file per the growth policy if the vector
+    /// is full.
+    ///
+    /// If growing the file fails the vector is left unchanged. If the sync
+    /// policy syncs here and that fails, the element is still appended.
+    pub fn push(&mut self, value: T) -> Result<()> {
+        let header: &FMVHeader = unsafe { &*self.header };
+        if header.magic != MAGIC {
+            return Err(FmvError::BadMagic);
+        }
+
+        let size = header.size(Relaxed);
+        if size >= self.capacity {
+            self.grow(size + 1)?;
+        }
+
+        unsafe { self.data.add(size).write(value) };
+        self.publish(size + 1, 1)
+    }
+
+    /// Appends all of `values`.
+    ///
+    /// The file is grown at most once and the new length is published after
+    /// all elements are copied. If growing the file fails the vector is left
+    /// unchanged.
+    pub fn extend_from_slice(&mut self, values: &[T]) -> Result<()> {
+        let header: &FMVHeader = unsafe { &*self.header };
+        if header.magic != MAGIC {
+            return Err(FmvError::BadMagic);
+        }
+
+        let size = header.size(Relaxed);
+        self.grow(size + values.len())?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(values.as_ptr(), self.data.add(size), values.len());
+        }
+        self.publish(size + values.len(), values.len())
+    }
+
+    /// Appends every element of `iter`.
+    ///
+    /// Capacity for the iterator's lower size bound is reserved up front and
+    /// the new length is published once at the end. If growing the file
+    /// fails, the elements written so far are kept.
+    pub fn try_extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<()> {
+        let header: &FMVHeader = unsafe { &*self.header };
+        if header.magic != MAGIC {
+            return Err(FmvError::BadMagic);
+        }
+
+        let iter = iter.into_iter();
+        let old_size = header.size(Relaxed);
+        let mut size = old_size;
+        self.grow(size + iter.size_hint().0)?;
+
+        let mut ret = Ok(());
+        for value in iter {
+            if size >= self.capacity {
+                ret = self.grow(size + 1);
+                if ret.is_err() {
+                    break;
+                }
+            }
+
+            unsafe { self.data.add(size).write(value) };
+            size += 1;
+        }
+
+        let published = self.publish(size, size - old_size);
+        ret.and(published)
+    }
+
+    /// Replaces the elements from index `at` on with `values`, growing or
+    /// shrinking the vector to fit.
+    ///
+    /// The overwritten elements are synced again on the next flush. A crash
+    /// before that can leave the range partly overwritten. If growing the
+    /// file fails the vector is left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// If `at` is greater than the length.
+    pub fn replace_tail(&mut self, at: usize, values: &[T]) -> Result<()> {
+        let len = self.len();
+        assert!(at <= len, "index {at} out of bounds");
+
+        let new_len = at + values.len();
+        self.grow(new_len)?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(values.as_ptr(), self.data.add(at), values.len());
+        }
+        self.dirty_from = self.dirty_from.min(at);
+
+        if new_len < len {
+            self.truncate(new_len);
+            return Ok(());
+        }
+        self.publish(new_len, new_len - len)
+    }
+
+    /// Stores the new length after `appended` elements were written, then
+    /// syncs if the policy asks for it.
+    fn publish(&mut self, size: usize, appended: usize) -> Result<()> {
+        let header: &FMVHeader = unsafe { &*self.header };
+        header.set_size(size, Release);
+
+        if self.lock_tail != 0 && HEADER_SIZE + size * size_of::<T>() >= self.locked.end {
+            // the elements are stored already, so rather than fail the append
+            // stop moving the window, and leave the error to the next flush
+            if let Err(err) = self.relock_tail() {
+                self.lock_tail = 0;
+                self.shared.report(err);
+            }
+        }
+
+        match self.policy {
+            SyncPolicy::Never => header.set_synced_size(size, Release),
+            SyncPolicy::OnDrop | SyncPolicy::EveryInterval(_) => {}
+            SyncPolicy::EveryN(n) => {
+                self.unsynced += appended;
+                if self.unsynced >= n {
+                    self.flush()?;
+                }
+            }
+            SyncPolicy::Always => self.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Moves the locked window to span `lock_tail` bytes either side of the
+    /// end of the vector, within the file. If locking fails the old window
+    /// stays locked.
+    fn relock_tail(&mut self) -> Result<()> {
+        let page = page_size();
+        let end = HEADER_SIZE + self.len() * size_of::<T>();
+        let file_end = HEADER_SIZE + self.capacity * size_of::<T>();
+
+        let start = end.saturating_sub(self.lock_tail).max(HEADER_SIZE);
+        let start = start - start % page;
+        let stop = round_up_to_page((end + self.lock_tail).min(file_end));
+
+        // locks do not nest, so only unlock what the new window leaves out
+        self.mapping.lock(start, stop - start)?;
+        let old = std::mem::replace(&mut self.locked, start..stop);
+        if old.start < start {
+            self.mapping
+                .unlock(old.start, old.end.min(start) - old.start);
+        }
+        if old.end > stop {
+            let from = old.start.max(stop);
+            self.mapping.unlock(from, old.end - from);
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the last element, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+
+        let value = unsafe { self.data.add(len - 1).read() };
+        self.truncate(len - 1);
+        Some(value)
+    }
+
+    /// Shortens the vector to `len` elements. Does nothing if it is already
+    /// shorter.
+    ///
+    /// Like [`Vec::truncate`], this does not change the capacity; call
+    /// [`shrink_to_fit`](Self::shrink_to_fit) to give the space back.
+    ///
+    /// If this cuts into the flushed elements, the header is synced before
+    /// returning, so a crash cannot bring them back. An error doing so is
+    /// reported by the next [`flush`](Self::flush).
+    pub fn truncate(&mut self, len: usize) {
+        let header: &FMVHeader = unsafe { &*self.header };
+        let _guard = self.shared.publish_lock();
+        let len = header.size(Relaxed).min(len);
+        header.set_size(len, Release);
+        self.lower_synced_size(len);
+
+        // elements pushed from here on land in already checksummed blocks
+        self.dirty_from = self.dirty_from.min(len);
+    }
+
+    /// Removes all elements, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Sets the length without touching the data.
+    ///
+    /// # Safety
+    ///
+    /// `len` must not exceed the capacity, and every element below `len`
+    /// must hold a valid `T`.
+    pub unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity);
+        let _guard = self.shared.publish_lock();
+        let header: &FMVHeader = &*self.header;
+        self.dirty_from = self.dirty_from.min(header.size(Relaxed).min(len));
+        header.set_size(len, Release);
+        self.lower_synced_size(len);
+    }
+
+    /// Lowers the synced length to at most `len` and syncs the header, as
+    /// the elements past `len` may be overwritten before the next flush. Call
+    /// with the publish lock held.
+    fn lower_synced_size(&self, len: usize) {
+        let header: &FMVHeader = unsafe { &*self.header };
+        if len >= header.synced_size(Relaxed) {
+            return;
+        }
+
+        header.set_synced_size(len, Release);
+        if self.policy != SyncPolicy::Never {
+            // the next flush writes the header again
+            if let Err(err) = self.mapping.sync_range(0, HEADER_SIZE, MS_SYNC) {
+                self.shared.report(err);
+            }
+        }
+    }
+
+    /// Truncates the file to the current length, releasing the space past it.
+    pub fn shrink_to_fit(&mut self) -> Result<()> {
+        let len = self.len();
+        if len == self.capacity {
+            return Ok(());
+        }
+
+        // keep the flusher out while the file shrinks under it
+        let _guard = self.shared.publish_lock();
+
+        // the on-disk synced length must not point past the new end of file
+        self.mapping.sync_range(0, HEADER_SIZE, MS_SYNC)?;
+
+        // drop the pages first so nothing touches them once they are gone
+        let new_size = HEADER_SIZE + len * size_of::<T>();
+        self.mapping.advise(
+            new_size,
+            (self.capacity - len) * size_of::<T>(),
+            MADV_DONTNEED,
+        );
+        self.file.set_len(new_size as u64)?;
+
+        self.capacity = len;
+        Ok(())
+    }
+
+    /// Deallocates the file blocks backing the elements in `range`, keeping
+    /// the length and file size.
+    ///
+    /// Only whole filesystem blocks inside the range are freed; afterwards
+    /// they read back as zeroes. This lets retention reclaim old data without
+    /// rewriting the file.
+    pub fn punch_hole(&mut self, range: Range<usize>) -> Result<()> {
+        assert!(range.start <= range.end && range.end <= self.capacity);
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        let offset = HEADER_SIZE + range.start * size_of::<T>();
+        let len = range.len() * size_of::<T>();
+
+        // the punched blocks read back as zeroes, so checksum them again
+        self.dirty_from = self.dirty_from.min(range.start);
+
+        let ret = unsafe {
+            fallocate(
+                self.file.as_raw_fd(),
+                FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE,
+                offset as i64,
+                len as i64,
+            )
+        };
+        if ret != 0 {
+            return Err(FmvError::FallocateFailed(FmvError::errno()));
+        }
+
+        Ok(())
+    }
+
+    /// Makes every element pushed so far durable.
+    ///
+    /// The data pages are `msync`ed first and only then is `synced_size`
+    /// advanced and the header synced, so the on-disk length never covers
+    /// data that has not reached the disk.
+    ///
+    /// With checksums enabled, blocks sealed or modified since the last
+    /// flush are then checksummed and the sidecar synced.
+    ///
+    /// Also reports any error the background flusher of
+    /// [`SyncPolicy::EveryInterval`] ran into since the last call.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(err) = self.shared.take_error() {
+            return Err(err);
+        }
+
+        self.shared.commit(self.dirty_from)?;
+        self.update_checksums()?;
+
+        self.dirty_from = usize::MAX;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Checksums the blocks sealed or modified since the last flush, if
+    /// checksums are enabled.
+    fn update_checksums(&mut self) -> Result<()> {
+        let Some(mut checksums) = self.checksums.take() else {
+            return Ok(());
+        };
+
+        let result = checksums.update(
+            self.as_bytes(),
+            self.dirty_from.saturating_mul(size_of::<T>()),
+        );
+        self.checksums = Some(checksums);
+        result
+    }
+
+    /// Synchronously writes the elements in `range` back to the file.
+    ///
+    /// This does not advance the synced length; use [`flush`](Self::flush)
+    /// to make appends survive a crash.
+    pub fn sync_range(&self, range: Range<usize>) -> Result<()> {
+        assert!(range.start <= range.end && range.end <= self.capacity);
+        self.mapping.sync_range(
+            HEADER_SIZE + range.start * size_of::<T>(),
+            range.len() * size_of::<T>(),
+            MS_SYNC,
+        )
+    }
+
+    /// Hints how the elements in `range` are about to be accessed.
+    ///
+    /// Growing past the reservation remaps the file and resets all hints.
+    pub fn advise(&self, range: Range<usize>, access: Access) -> Result<()> {
+        assert!(range.start <= range.end && range.end <= self.capacity);
+        self.mapping.advise_elements(range, size_of::<T>(), access)
+    }
+
+    /// Keeps CRC32C checksums of the data in `sidecar`, one per
+    /// [`DEFAULT_BLOCK_SIZE`] bytes of elements.
+    ///
+    /// An empty `sidecar` is initialized; an existing one must have been
+    /// written for this file. Checksums are computed by
+    /// [`flush`](Self::flush) and on drop, and only cover whole blocks, so
+    /// the tail pushed since then is unchecked. Blocks changed through
+    /// `as_mut_slice`, [`set`](Self::set), [`punch_hole`](Self::punch_hole)
+    /// or refilled after a truncation are checksummed again on the next
+    /// flush.
+    pub fn enable_checksums(&mut self, sidecar: File) -> Result<()> {
+        let mut checksums = Checksums::open(sidecar, DEFAULT_BLOCK_SIZE)?;
+        checksums.update(self.as_bytes(), usize::MAX)?;
+        self.checksums = Some(checksums);
+        Ok(())
+    }
+
+    /// Checks every checksummed block against its CRC32C and returns the
+    /// element ranges of the corrupt ones.
+    ///
+    /// Fails with [`FmvError::ChecksumsDisabled`] unless
+    /// [`enable_checksums`](Self::enable_checksums) was called.
+    pub fn verify(&self) -> Result<Vec<Range<usize>>> {
+        let Some(checksums) = &self.checksums else {
+            return Err(FmvError::ChecksumsDisabled);
+        };
+
+        let corrupt = checksums.verify(self.as_bytes())?;
+        Ok(corrupt
+            .into_iter()
+            .map(|bytes| checksum::element_range(bytes, size_of::<T>()))
+            .collect())
+    }
+
+    /// The current sync policy. Defaults to [`SyncPolicy::OnDrop`].
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Switches to `policy`, starting or stopping the background flusher as
+    /// needed.
+    pub fn set_sync_policy(&mut self, policy: SyncPolicy) {
+        self.flusher = None;
+        if let SyncPolicy::EveryInterval(interval) = policy {
+            self.flusher = Some(Flusher::spawn(self.shared.clone(), interval));
+        }
+
+        self.policy = policy;
+        self.unsynced = 0;
+    }
+
+    /// Makes room for at least `additional` more elements, growing the file
+    /// according to the growth policy.
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        self.grow(self.len() + additional)
+    }
+
+    /// Makes room for exactly `additional` more elements, ignoring the
+    /// growth policy.
+    pub fn reserve_exact(&mut self, additional: usize) -> Result<()> {
+        let min_cap = self.len() + additional;
+        if min_cap <= self.capacity {
+            return Ok(());
+        }
+        self.grow_to(min_cap)
+    }
+
+    /// The current growth policy. Defaults to [`GrowthPolicy::Doubling`].
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.growth
+    }
+
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        self.growth = policy;
+    }
+
+    /// Grows the file per the growth policy until it holds at least
+    /// `min_cap` elements.
+    fn grow(&mut self, min_cap: usize) -> Result<()> {
+        if min_cap <= self.capacity {
+            return Ok(());
+        }
+
+        let new_cap = self
+            .growth
+            .next_capacity(self.capacity, min_cap, size_of::<T>());
+        self.grow_to(new_cap)
+    }
+
+    /// Grows the file to exactly `new_cap` elements.
+    fn grow_to(&mut self, new_cap: usize) -> Result<()> {
+        let old_size = size_of::<T>() * self.capacity + HEADER_SIZE;
+        let size_diff = size_of::<T>() * (new_cap - self.capacity);
+
+        let ret = unsafe { fallocate(self.file.as_raw_fd(), 0, old_size as i64, size_diff as i64) };
+        if ret != 0 {
+            return Err(FmvError::FallocateFailed(FmvError::errno()));
+        }
+
+        let new_size = old_size + size_diff;
+        if new_size > self.mapping.len() {
+            self.remap(round_up_to_page(new_size.max(self.mapping.len() * 2)))?;
+        }
+
+        // only the new space: older data keeps whatever `advise` set
+        self.mapping.advise(old_size, size_diff, MADV_SEQUENTIAL);
+
+        self.capacity = new_cap;
+        Ok(())
+    }
+
+    /// Moves the mapping to a region of `len` bytes.
+    fn remap(&mut self, len: usize) -> Result<()> {
+        // the flusher must not sync through the old address
+        let _guard = self.shared.publish_lock();
+
+        self.mapping.remap(len)?;
+        self.locked = 0..0;
+        if self.huge_pages {
+            self.mapping.advise(0, len, MADV_HUGEPAGE);
+        }
+        self.header = self.mapping.as_ptr() as *mut FMVHeader;
+        self.data = unsafe { self.mapping.as_ptr().add(HEADER_SIZE) } as *mut T;
+        self.shared.set_base(self.mapping.as_ptr());
+        Ok(())
+    }
+
+    /// Number of elements pushed so far.
+    pub fn len(&self) -> usize {
+        unsafe { (*self.header).size(Relaxed) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The pushed elements, borrowed straight from the mapping.
+    ///
+    /// Slice methods such as `get`, `iter` and range indexing are also
+    /// available on the vector itself through `Deref` and `DerefMut`.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.data, self.len()) }
+    }
+
+    /// The pushed elements as raw bytes; `Pod` types have no padding, so
+    /// every byte is initialized.
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data as *const u8, self.len() * size_of::<T>()) }
+    }
+
+    /// Mutable view of the pushed elements.
+    ///
+    /// Which elements the caller writes cannot be tracked, so the next
+    /// [`flush`](Self::flush) syncs the whole file again, and checksums it if
+    /// checksums are enabled. Use [`set`](Self::set) to overwrite a few
+    /// elements near the end instead.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // we cannot tell which elements the caller will write
+        self.dirty_from = 0;
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.len()) }
+    }
+
+    /// Overwrites the element at `index`.
+    ///
+    /// Only the elements from `index` on are synced and checksummed again on
+    /// the next flush.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) {
+        assert!(index < self.len(), "index {index} out of bounds");
+        unsafe { self.data.add(index).write(value) };
+        self.dirty_from = self.dirty_from.min(index);
+    }
+
+    /// Number of elements the file has room for before it must grow.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T: Pod> Deref for FileMappedVector<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Pod> DerefMut for FileMappedVector<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Pod> AsRef<[T]> for FileMappedVector<T> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Pod> AsMut<[T]> for FileMappedVector<T> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Pod> Extend<T> for FileMappedVector<T> {
+    /// See [`try_extend`](Self::try_extend).
+    ///
+    /// # Panics
+    ///
+    /// If the file cannot be grown. Use `try_extend` to handle that instead.
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.try_extend(iter)
+            .expect("failed to extend FileMappedVector");
+    }
+}
+
+impl<'a, T: Pod> Extend<&'a T> for FileMappedVector<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<'a, T: Pod> IntoIterator for &'a FileMappedVector<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Pod> Storage for FileMappedVector<T> {
+    type Item = T;
+
+    fn len(&self) -> usize {
+        FileMappedVector::len(self)
+    }
+
+    fn push(&mut self, value: T) -> Result<()> {
+        FileMappedVector::push(self, value)
+    }
+
+    fn extend_from_slice(&mut self, values: &[T]) -> Result<()> {
+        FileMappedVector::extend_from_slice(self, values)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        FileMappedVector::flush(self)
+    }
+}
+
+impl<T: Pod> Drop for FileMappedVector<T> {
+    fn drop(&mut self) {
+        self.flusher = None;
+
+        if self.policy == SyncPolicy::Never {
+            // nothing is synced, but the checksums must match the data
+            let _ = self.update_checksums();
+            unsafe {
+                let header = &mut *self.header;
+                header.flags.set(header.flags.get() & !FLAG_DIRTY);
+                header.writer_pid.set(0);
+            }
+            return;
+        }
+
+        // nothing useful to do with an error while dropping, but a failed
+        // flush must leave the file marked dirty so the next open recovers
+        if self.flush().is_ok() {
+            unsafe {
+                let header = &mut *self.header;
+                header.flags.set(header.flags.get() & !FLAG_DIRTY);
+                header.writer_pid.set(0);
+            }
+            let _ = self.mapping.sync_range(0, HEADER_SIZE, MS_SYNC);
+        }
+        let _ = self.file.sync_all();
+    }
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use common::TempFile;
+use tsdb_rs::{BufferedOptions, FileMappedSlice, FmvError, GrowthPolicy};
+
+/// Caps the size of files this process writes at `bytes`, failing writes
+/// past it with `EFBIG` instead of killing the process.
+fn limit_file_size(bytes: u64) {
+    unsafe {
+        libc::signal(libc::SIGXFSZ, libc::SIG_IGN);
+        let limit = libc::rlimit {
+            rlim_cur: bytes,
+            rlim_max: bytes,
+        };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_FSIZE, &limit), 0);
+    }
+}
+
+fn options(direct: bool, io_uring: bool) -> BufferedOptions {
+    let mut opts = BufferedOptions::new();
+    opts.buffer_size(4096).direct(direct).io_uring(io_uring);
+    opts
+}
+
+#[test]
+fn reopens_in_every_mode() {
+    for (direct, io_uring) in [(false, false), (true, false), (false, true), (true, true)] {
+        let file = TempFile::new(&format!("reopens_in_every_mode-{direct}-{io_uring}"));
+        let opts = options(direct, io_uring);
+
+        // batches of odd sizes leave the end in the middle of a block, which
+        // O_DIRECT has to read back and write again
+        let mut vec = opts.open::<u64>(file.open()).unwrap();
+        vec.extend_from_slice(&(0..1000).collect::<Vec<_>>())
+            .unwrap();
+        vec.flush().unwrap();
+        vec.push(1000).unwrap();
+        drop(vec);
+
+        let mut vec = opts.open::<u64>(file.open()).unwrap();
+        assert_eq!(vec.len(), 1001);
+        for start in (1001..3036).step_by(37) {
+            vec.extend_from_slice(&(start..start + 37).collect::<Vec<_>>())
+                .unwrap();
+        }
+        drop(vec);
+
+        let slice = FileMappedSlice::<u64>::new(&file.open()).unwrap();
+        assert!(
+            slice.iter().copied().eq(0..3036),
+            "direct: {direct}, io_uring: {io_uring}"
+        );
+    }
+}
+
+/// Writer half of `keeps_length_after_failed_write`, run in a child process
+/// with a file size limit.
+#[test]
+fn fsize_writer_child() {
+    let Some(path) = common::child_path() else {
+        return;
+    };
+
+    // the second buffer is written past the limit
+    limit_file_size(10_000);
+
+    let direct = std::env::var_os("DIRECT").is_some();
+    let mut vec = options(direct, false)
+        .open::<u64>(common::open(&path))
+        .unwrap();
+    vec.push(1).unwrap();
+    assert!(matches!(
+        vec.extend_from_slice(&[7; 1100]),
+        Err(FmvError::FallocateFailed(libc::EFBIG))
+    ));
+    assert_eq!(vec.len(), 1);
+    vec.push(999).unwrap();
+}
+
+#[test]
+fn keeps_length_after_failed_write() {
+    for direct in [false, true] {
+        let file = TempFile::new(&format!("keeps_length_after_failed_write-{direct}"));
+
+        let mut child = common::child("fsize_writer_child", &file.0);
+        if direct {
+            child.env("DIRECT", "1");
+        }
+        assert!(child.status().unwrap().success());
+
+        let slice = FileMappedSlice::<u64>::new(&file.open()).unwrap();
+        assert_eq!(slice.as_slice(), &[1, 999], "direct: {direct}");
+    }
+}
+
+/// Writer half of `poisons_after_failed_uring_write`, run in a child process.
+#[test]
+fn uring_writer_child() {
+    let Some(path) = common::child_path() else {
+        return;
+    };
+
+    let mut vec = options(false, true)
+        .growth_policy(GrowthPolicy::Preallocate(100_000))
+        .open::<u64>(common::open(&path))
+        .unwrap();
+    if !vec.uses_io_uring() {
+        return;
+    }
+    vec.extend_from_slice(&[1; 100]).unwrap();
+    vec.flush().unwrap();
+
+    // the file is already allocated, so only the writes fail
+    limit_file_size(10_000);
+    let failed = (0..100).any(|_| vec.extend_from_slice(&[2; 512]).is_err());
+    assert!(failed);
+    assert!(matches!(vec.push(3), Err(FmvError::Poisoned)));
+    assert!(matches!(vec.flush(), Err(FmvError::Poisoned)));
+}
+
+#[test]
+fn poisons_after_failed_uring_write() {
+    let file = TempFile::new("poisons_after_failed_uring_write");
+
+    let status = common::child("uring_writer_child", &file.0)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // everything after the last flush is rolled back
+    let slice = FileMappedSlice::<u64>::new(&file.open()).unwrap();
+    assert!(slice.is_empty() || slice.as_slice() == [1; 100]);
+}
+
+#[test]
+fn starts_at_initial_capacity() {
+    let file = TempFile::new("starts_at_initial_capacity-default");
+    let vec = BufferedOptions::new().open::<u64>(file.open()).unwrap();
+    assert_eq!(vec.capacity(), 32);
+
+    let file = TempFile::new("starts_at_initial_capacity");
+    let vec = BufferedOptions::new()
+        .growth_policy(GrowthPolicy::Preallocate(1000))
+        .open::<u64>(file.open())
+        .unwrap();
+    assert_eq!(vec.capacity(), 1000);
+    assert_eq!(file.0.metadata().unwrap().len(), 4096 + 1000 * 8);
+    drop(vec);
+
+    let vec = BufferedOptions::new()
+        .capacity(1500)
+        .open::<u64>(file.open())
+        .unwrap();
+    assert_eq!(vec.capacity(), 2000);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use std::{fs::File, os::unix::fs::FileExt};
+
+use common::TempFile;
+use tsdb_rs::{FileMappedSlice, FileMappedVector, SyncPolicy};
+
+// elements per checksummed block
+const BLOCK: usize = 64 * 1024 / size_of::<u64>();
+
+fn open(data: &TempFile, sidecar: &TempFile) -> FileMappedVector<u64> {
+    let mut vec = FileMappedVector::new(data.open()).unwrap();
+    vec.enable_checksums(sidecar.open()).unwrap();
+    vec
+}
+
+#[test]
+fn detects_corrupt_blocks() {
+    let data = TempFile::new("detects_corrupt_blocks");
+    let sidecar = TempFile::new("detects_corrupt_blocks.crc");
+
+    let mut vec = open(&data, &sidecar);
+    vec.extend(0..3 * BLOCK as u64);
+    vec.flush().unwrap();
+    assert!(vec.verify().unwrap().is_empty());
+
+    // flip a byte in the second block behind the vector's back
+    let offset = 4096 + (BLOCK + 10) * size_of::<u64>();
+    data.open().write_all_at(&[0xff], offset as u64).unwrap();
+    assert_eq!(vec.verify().unwrap(), vec![(BLOCK..2 * BLOCK)]);
+    drop(vec);
+
+    let slice = FileMappedSlice::<u64>::new(&data.open()).unwrap();
+    assert_eq!(
+        slice.verify(&File::open(&sidecar.0).unwrap()).unwrap(),
+        vec![(BLOCK..2 * BLOCK)]
+    );
+}
+
+#[test]
+fn rechecksums_rewritten_blocks() {
+    let data = TempFile::new("rechecksums_rewritten_blocks");
+    let sidecar = TempFile::new("rechecksums_rewritten_blocks.crc");
+
+    let mut vec = open(&data, &sidecar);
+    vec.extend(0..3 * BLOCK as u64);
+    vec.flush().unwrap();
+
+    // refilled after a truncation, without a flush in between
+    vec.truncate(BLOCK);
+    vec.extend(0..2 * BLOCK as u64);
+    vec.flush().unwrap();
+    assert!(vec.verify().unwrap().is_empty());
+
+    vec.set(BLOCK + 1, 42);
+    vec[10] = 42;
+    vec.flush().unwrap();
+    assert!(vec.verify().unwrap().is_empty());
+}
+
+#[test]
+fn checksums_on_drop_without_syncing() {
+    let data = TempFile::new("checksums_on_drop_without_syncing");
+    let sidecar = TempFile::new("checksums_on_drop_without_syncing.crc");
+
+    let mut vec = open(&data, &sidecar);
+    vec.extend(0..2 * BLOCK as u64);
+    vec.flush().unwrap();
+
+    vec.set_sync_policy(SyncPolicy::Never);
+    vec.set(5, 99);
+    drop(vec);
+
+    let slice = FileMappedSlice::<u64>::new(&data.open()).unwrap();
+    assert_eq!(slice[5], 99);
+    assert!(slice
+        .verify(&File::open(&sidecar.0).unwrap())
+        .unwrap()
+        .is_empty());
+}
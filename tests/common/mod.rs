@@ -0,0 +1,78 @@
+// each test crate uses only some of these
+#![allow(dead_code)]
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Passes a child process the path it works on, see [`child`].
+const CHILD_PATH_ENV: &str = "TSDB_RS_CHILD_PATH";
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tsdb-rs-{}-{name}", std::process::id()))
+}
+
+/// Opens `path` for reading and writing.
+pub fn open(path: &Path) -> File {
+    File::options().read(true).write(true).open(path).unwrap()
+}
+
+/// A fresh, empty file in the temp dir, removed again on drop.
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        let path = temp_path(name);
+        File::create(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn open(&self) -> File {
+        open(&self.0)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A path in the temp dir for a directory, such as a series, that is
+/// removed again on drop.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = temp_path(name);
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A command running only the test `name` of this test binary, in a child
+/// process that finds `path` through [`child_path`].
+///
+/// Tests meant to run as children return right away when `child_path` is
+/// `None`, so they pass when the whole binary runs.
+pub fn child(name: &str, path: &Path) -> Command {
+    let mut command = Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["--exact", name])
+        .env(CHILD_PATH_ENV, path)
+        .stdout(Stdio::null());
+    command
+}
+
+/// The path given to [`child`], if this process is one.
+pub fn child_path() -> Option<PathBuf> {
+    std::env::var_os(CHILD_PATH_ENV).map(PathBuf::from)
+}
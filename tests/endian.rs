@@ -0,0 +1,59 @@
+mod common;
+
+use std::{mem::offset_of, os::unix::fs::FileExt};
+
+use common::TempFile;
+use tsdb_rs::{
+    header::{BYTE_ORDER_BIG, BYTE_ORDER_LITTLE, BYTE_ORDER_NATIVE},
+    FMVHeader, FileMappedSlice, FileMappedVector, FmvError, Le,
+};
+
+/// Marks `file` as written on a host of the other byte order.
+fn flip_byte_order(file: &TempFile) {
+    let other = match BYTE_ORDER_NATIVE {
+        BYTE_ORDER_LITTLE => BYTE_ORDER_BIG,
+        _ => BYTE_ORDER_LITTLE,
+    };
+    file.open()
+        .write_all_at(&[other], offset_of!(FMVHeader, byte_order) as u64)
+        .unwrap();
+}
+
+#[test]
+fn rejects_native_types_from_other_byte_order() {
+    let file = TempFile::new("rejects_native_types_from_other_byte_order");
+    let mut vec = FileMappedVector::<Le<u64>>::new(file.open()).unwrap();
+    vec.extend([1, 2, 3].map(Le::new));
+    drop(vec);
+    flip_byte_order(&file);
+
+    assert!(matches!(
+        FileMappedVector::<u64>::new(file.open()),
+        Err(FmvError::ByteOrderMismatch)
+    ));
+    assert!(matches!(
+        FileMappedSlice::<u64>::new(&file.open()),
+        Err(FmvError::ByteOrderMismatch)
+    ));
+
+    let slice = FileMappedSlice::<Le<u64>>::new(&file.open()).unwrap();
+    assert!(slice.iter().map(|value| value.get()).eq(1..=3));
+    drop(slice);
+
+    let mut vec = FileMappedVector::<Le<u64>>::new(file.open()).unwrap();
+    vec.push(Le::new(4)).unwrap();
+    assert!(vec.iter().map(|value| value.get()).eq(1..=4));
+}
+
+#[test]
+fn accepts_bytes_from_other_byte_order() {
+    let file = TempFile::new("accepts_bytes_from_other_byte_order");
+    FileMappedVector::<u8>::new(file.open())
+        .unwrap()
+        .extend_from_slice(b"abc")
+        .unwrap();
+    flip_byte_order(&file);
+
+    let vec = FileMappedVector::<u8>::new(file.open()).unwrap();
+    assert_eq!(vec.as_slice(), b"abc");
+}
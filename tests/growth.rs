@@ -0,0 +1,92 @@
+mod common;
+
+use common::TempFile;
+use tsdb_rs::{FileMappedVector, GrowthPolicy};
+
+/// Capacities a vector of `u64` goes through while `count` elements are
+/// pushed one at a time under `policy`.
+fn capacities(name: &str, policy: GrowthPolicy, count: u64) -> Vec<usize> {
+    let file = TempFile::new(name);
+    let mut vec = FileMappedVector::<u64>::options()
+        .growth_policy(policy)
+        .open(file.open())
+        .unwrap();
+
+    let mut capacities = vec![vec.capacity()];
+    for value in 0..count {
+        vec.push(value).unwrap();
+        if vec.capacity() != *capacities.last().unwrap() {
+            capacities.push(vec.capacity());
+        }
+    }
+    assert!(vec.as_slice().iter().copied().eq(0..count));
+    capacities
+}
+
+#[test]
+fn grows_per_policy() {
+    assert_eq!(
+        capacities("grows_doubling", GrowthPolicy::Doubling, 200),
+        [32, 64, 128, 256]
+    );
+    // 80 bytes round up to 10 elements
+    assert_eq!(
+        capacities("grows_chunk", GrowthPolicy::Chunk(80), 60),
+        [32, 42, 52, 62]
+    );
+    // steps of at most 128 bytes, or 16 elements
+    assert_eq!(
+        capacities("grows_capped", GrowthPolicy::CappedDoubling(128), 70),
+        [32, 48, 64, 80]
+    );
+    assert_eq!(
+        capacities("grows_preallocated", GrowthPolicy::Preallocate(100), 250),
+        [100, 200, 400]
+    );
+}
+
+#[test]
+fn reserves_capacity() {
+    let file = TempFile::new("reserves_capacity");
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    vec.extend(0..10);
+
+    // already room for them
+    vec.reserve(20).unwrap();
+    assert_eq!(vec.capacity(), 32);
+
+    // doubling falls short of 110, so the policy grows to exactly that
+    vec.reserve(100).unwrap();
+    assert_eq!(vec.capacity(), 110);
+    vec.reserve(101).unwrap();
+    assert_eq!(vec.capacity(), 220);
+
+    vec.reserve_exact(300).unwrap();
+    assert_eq!(vec.capacity(), 310);
+    vec.reserve_exact(10).unwrap();
+    assert_eq!(vec.capacity(), 310);
+    drop(vec);
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert_eq!(vec.capacity(), 310);
+    assert!(vec.as_slice().iter().copied().eq(0..10));
+}
+
+#[test]
+fn opens_with_capacity() {
+    let file = TempFile::new("opens_with_capacity");
+    let mut vec = FileMappedVector::<u64>::with_capacity(file.open(), 1000).unwrap();
+    assert_eq!(vec.capacity(), 1000);
+    assert_eq!(file.0.metadata().unwrap().len(), 4096 + 1000 * 8);
+    vec.extend(0..10);
+    drop(vec);
+
+    // smaller than the file: left as is
+    let vec = FileMappedVector::<u64>::with_capacity(file.open(), 100).unwrap();
+    assert_eq!(vec.capacity(), 1000);
+    drop(vec);
+
+    let vec = FileMappedVector::<u64>::with_capacity(file.open(), 1500).unwrap();
+    assert_eq!(vec.capacity(), 2000);
+    assert!(vec.as_slice().iter().copied().eq(0..10));
+}
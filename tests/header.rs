@@ -0,0 +1,105 @@
+mod common;
+
+use std::io::Write;
+
+use common::TempFile;
+use tsdb_rs::{FileMappedSlice, FileMappedVector, FmvError, MAGIC};
+
+/// Writes a version 0 file holding `values`: magic, size, then zeroes up to
+/// the first element.
+fn write_version_0(file: &TempFile, values: &[u64]) {
+    let mut bytes = vec![0; 4096];
+    bytes[..8].copy_from_slice(&MAGIC);
+    bytes[8..16].copy_from_slice(&(values.len() as u64).to_le_bytes());
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    file.open().write_all(&bytes).unwrap();
+}
+
+#[test]
+fn rejects_bad_magic() {
+    let file = TempFile::new("rejects_bad_magic");
+    file.open().write_all(&[0xff; 4096 + 8]).unwrap();
+
+    assert!(matches!(
+        FileMappedVector::<u64>::new(file.open()),
+        Err(FmvError::BadMagic)
+    ));
+    assert!(matches!(
+        FileMappedSlice::<u64>::new(&file.open()),
+        Err(FmvError::BadMagic)
+    ));
+}
+
+#[test]
+fn rejects_truncated_header() {
+    let file = TempFile::new("rejects_truncated_header");
+    file.open().write_all(&MAGIC).unwrap();
+
+    assert!(matches!(
+        FileMappedVector::<u64>::new(file.open()),
+        Err(FmvError::TruncatedHeader)
+    ));
+    assert!(matches!(
+        FileMappedSlice::<u64>::new(&file.open()),
+        Err(FmvError::TruncatedHeader)
+    ));
+}
+
+#[test]
+fn rejects_other_element_types() {
+    let file = TempFile::new("rejects_other_element_types");
+    FileMappedVector::<u64>::with_type_tag(file.open(), 7)
+        .unwrap()
+        .push(1)
+        .unwrap();
+
+    assert!(matches!(
+        FileMappedVector::<u32>::with_type_tag(file.open(), 7),
+        Err(FmvError::TypeMismatch {
+            field: "element size",
+            expected: 4,
+            found: 8
+        })
+    ));
+    assert!(matches!(
+        FileMappedVector::<u64>::with_type_tag(file.open(), 8),
+        Err(FmvError::TypeMismatch {
+            field: "type tag",
+            expected: 8,
+            found: 7
+        })
+    ));
+    assert_eq!(
+        FileMappedVector::<u64>::with_type_tag(file.open(), 7)
+            .unwrap()
+            .as_slice(),
+        &[1]
+    );
+}
+
+#[test]
+fn rejects_size_not_multiple_of_element() {
+    let file = TempFile::new("rejects_size_not_multiple_of_element");
+    write_version_0(&file, &[1, 2]);
+
+    assert!(matches!(
+        FileMappedVector::<[u8; 3]>::new(file.open()),
+        Err(FmvError::SizeNotMultipleOfElement)
+    ));
+
+    // the failed open must not have claimed the file for `[u8; 3]`
+    assert_eq!(
+        FileMappedSlice::<u64>::new(&file.open())
+            .unwrap()
+            .as_slice(),
+        &[1, 2]
+    );
+    assert_eq!(
+        FileMappedVector::<u64>::new(file.open())
+            .unwrap()
+            .as_slice(),
+        &[1, 2]
+    );
+}
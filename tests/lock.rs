@@ -0,0 +1,79 @@
+mod common;
+
+use std::fs::File;
+
+use common::TempFile;
+use tsdb_rs::{BufferedOptions, FileMappedSlice, FileMappedVector, FmvError};
+
+#[test]
+fn reports_writer_pid() {
+    let file = TempFile::new("reports_writer_pid");
+    let pid = Some(std::process::id());
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert!(matches!(
+        FileMappedVector::<u64>::try_new(file.open()),
+        Err(FmvError::Locked { pid: holder }) if holder == pid
+    ));
+    assert!(matches!(
+        FileMappedSlice::<u64>::try_new(&file.open()),
+        Err(FmvError::Locked { pid: holder }) if holder == pid
+    ));
+
+    drop(vec);
+    FileMappedSlice::<u64>::try_new(&file.open()).unwrap();
+}
+
+#[test]
+fn slices_keep_writers_out() {
+    let file = TempFile::new("slices_keep_writers_out");
+    drop(FileMappedVector::<u64>::new(file.open()).unwrap());
+
+    // slices opened from one file must not share a lock
+    let shared = file.open();
+    let first = FileMappedSlice::<u64>::try_new(&shared).unwrap();
+    let second = FileMappedSlice::<u64>::try_new(&shared).unwrap();
+    drop(first);
+    assert!(matches!(
+        FileMappedVector::<u64>::try_new(file.open()),
+        Err(FmvError::Locked { .. })
+    ));
+
+    drop(second);
+    FileMappedVector::<u64>::try_new(file.open()).unwrap();
+}
+
+#[test]
+fn cloned_handles_do_not_share_the_lock() {
+    let file = TempFile::new("cloned_handles_do_not_share_the_lock");
+
+    let handle = file.open();
+    let vec = FileMappedVector::<u64>::try_new(handle.try_clone().unwrap()).unwrap();
+    assert!(matches!(
+        FileMappedVector::<u64>::try_new(handle.try_clone().unwrap()),
+        Err(FmvError::Locked { .. })
+    ));
+    assert!(matches!(
+        BufferedOptions::new().try_open::<u64>(handle.try_clone().unwrap()),
+        Err(FmvError::Locked { .. })
+    ));
+    drop(vec);
+
+    let vec = BufferedOptions::new()
+        .try_open::<u64>(handle.try_clone().unwrap())
+        .unwrap();
+    assert!(matches!(
+        FileMappedVector::<u64>::try_new(handle),
+        Err(FmvError::Locked { .. })
+    ));
+    drop(vec);
+}
+
+#[test]
+fn rejects_read_only_handles() {
+    let file = TempFile::new("rejects_read_only_handles");
+    assert!(matches!(
+        FileMappedVector::<u64>::new(File::open(&file.0).unwrap()),
+        Err(FmvError::ReadOnly)
+    ));
+}
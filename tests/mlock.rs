@@ -0,0 +1,53 @@
+mod common;
+
+use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+use common::TempFile;
+use tsdb_rs::{FileMappedSlice, FileMappedVector, FmvError};
+
+/// Writer half of `appends_past_memlock_limit`, run in a child process with
+/// a 64 KiB `RLIMIT_MEMLOCK`.
+#[test]
+fn mlock_writer_child() {
+    let Some(path) = common::child_path() else {
+        return;
+    };
+
+    unsafe {
+        let limit = libc::rlimit {
+            rlim_cur: 64 * 1024,
+            rlim_max: 64 * 1024,
+        };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit), 0);
+        // root ignores the limit, so run as nobody
+        if libc::geteuid() == 0 {
+            assert_eq!(libc::setuid(65534), 0);
+        }
+    }
+
+    let mut vec = FileMappedVector::<u64>::options()
+        .lock_tail(48 * 1024)
+        .open::<u64>(common::open(&path))
+        .unwrap();
+    for value in 0..200_000 {
+        vec.push(value).unwrap();
+    }
+    assert_eq!(vec.len(), 200_000);
+
+    assert!(matches!(vec.flush(), Err(FmvError::MlockFailed(_))));
+    vec.flush().unwrap();
+}
+
+#[test]
+fn appends_past_memlock_limit() {
+    let file = TempFile::new("appends_past_memlock_limit");
+    std::fs::set_permissions(&file.0, Permissions::from_mode(0o666)).unwrap();
+
+    let status = common::child("mlock_writer_child", &file.0)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let slice = FileMappedSlice::<u64>::new(&file.open()).unwrap();
+    assert!(slice.iter().copied().eq(0..200_000));
+}
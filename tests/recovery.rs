@@ -0,0 +1,47 @@
+mod common;
+
+use std::process::Stdio;
+
+use common::TempFile;
+use tsdb_rs::{FileMappedReader, FileMappedSlice, FileMappedVector};
+
+/// Writer half of `rolls_back_after_crash`, run in a child process: flushes
+/// three elements, appends two more and dies without cleaning up.
+#[test]
+fn crash_writer_child() {
+    let Some(path) = common::child_path() else {
+        return;
+    };
+
+    let mut vec = FileMappedVector::<u64>::new(common::open(&path)).unwrap();
+    vec.extend_from_slice(&[1, 2, 3]).unwrap();
+    vec.flush().unwrap();
+    vec.extend_from_slice(&[4, 5]).unwrap();
+    std::process::abort();
+}
+
+#[test]
+fn rolls_back_after_crash() {
+    let file = TempFile::new("rolls_back_after_crash");
+
+    let status = common::child("crash_writer_child", &file.0)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+
+    // readers see what a writer would recover, not the unflushed tail
+    let slice = FileMappedSlice::<u64>::new(&file.open()).unwrap();
+    assert_eq!(slice.as_slice(), &[1, 2, 3]);
+    drop(slice);
+    let mut reader = FileMappedReader::<u64>::new(&file.open()).unwrap();
+    assert_eq!(reader.refresh().unwrap(), 3);
+
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert_eq!(vec.as_slice(), &[1, 2, 3]);
+    vec.push(6).unwrap();
+
+    // once a new writer has recovered the file, the reader follows it again
+    assert_eq!(reader.refresh().unwrap(), 4);
+    assert_eq!(reader.as_slice(), &[1, 2, 3, 6]);
+}
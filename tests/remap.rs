@@ -0,0 +1,89 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TempFile;
+use tsdb_rs::{FileMappedVector, SyncPolicy};
+
+#[test]
+fn grows_past_tiny_reservation() {
+    let file = TempFile::new("grows_past_tiny_reservation");
+
+    let mut vec = FileMappedVector::<u64>::options()
+        .reservation(4096)
+        .noreserve(true)
+        .open::<u64>(file.open())
+        .unwrap();
+    for i in 0..100_000 {
+        vec.push(i).unwrap();
+    }
+    vec.extend_from_slice(&[100_000, 100_001]).unwrap();
+    assert!(vec.iter().copied().eq(0..100_002));
+    drop(vec);
+
+    // the file is now larger than the reservation
+    let vec = FileMappedVector::<u64>::options()
+        .reservation(4096)
+        .open::<u64>(file.open())
+        .unwrap();
+    assert!(vec.iter().copied().eq(0..100_002));
+}
+
+#[test]
+fn remaps_under_background_flusher() {
+    let file = TempFile::new("remaps_under_background_flusher");
+
+    let mut vec = FileMappedVector::<u64>::options()
+        .reservation(4096)
+        .sync_policy(SyncPolicy::EveryInterval(Duration::from_millis(1)))
+        .open::<u64>(file.open())
+        .unwrap();
+    for batch in 0..200 {
+        let start = batch * 1000;
+        vec.extend(start..start + 1000);
+    }
+    vec.flush().unwrap();
+    drop(vec);
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert!(vec.iter().copied().eq(0..200_000));
+}
+
+/// `VmFlags` of the mapping containing `addr`, from `/proc/self/smaps`.
+fn vm_flags(addr: usize) -> String {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let mut inside = false;
+    for line in smaps.lines() {
+        if let Some((range, _)) = line.split_once(' ') {
+            if let Some((start, end)) = range.split_once('-') {
+                if let (Ok(start), Ok(end)) = (
+                    usize::from_str_radix(start, 16),
+                    usize::from_str_radix(end, 16),
+                ) {
+                    inside = (start..end).contains(&addr);
+                    continue;
+                }
+            }
+        }
+        if let Some(flags) = line.strip_prefix("VmFlags:").filter(|_| inside) {
+            return flags.to_string();
+        }
+    }
+    panic!("no mapping at {addr:#x}");
+}
+
+#[test]
+fn keeps_huge_pages_after_remap() {
+    let file = TempFile::new("keeps_huge_pages_after_remap");
+
+    let mut vec = FileMappedVector::<u64>::options()
+        .reservation(4096)
+        .huge_pages(true)
+        .open::<u64>(file.open())
+        .unwrap();
+    vec.extend(0..100_000);
+
+    // `hg` is how smaps shows MADV_HUGEPAGE
+    let flags = vm_flags(vec.as_ptr() as usize);
+    assert!(flags.split_whitespace().any(|flag| flag == "hg"), "{flags}");
+}
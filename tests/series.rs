@@ -0,0 +1,267 @@
+mod common;
+
+use std::fs::File;
+
+use common::TempDir;
+use tsdb_rs::{
+    aggregate::aggregate,
+    series::{MERGE_DIR, TIMESTAMPS_FILE, TIMESTAMPS_TAG},
+    Aggregation, DuplicatePolicy, FileMappedVector, FmvError, OutOfOrderPolicy, Points, Series,
+};
+
+#[test]
+fn reopens_with_points() {
+    let dir = TempDir::new("reopens_with_points");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    for i in 0..1000 {
+        series.append(i, i as f64 / 2.0).unwrap();
+    }
+    drop(series);
+
+    let series = Series::open(&dir.0).unwrap();
+    assert_eq!(series.len(), 1000);
+    assert!(series.timestamps().iter().copied().eq(0..1000));
+    assert_eq!(series.values()[999], 499.5);
+}
+
+#[test]
+fn drops_torn_append() {
+    let dir = TempDir::new("drops_torn_append");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    for i in 0..10 {
+        series.append(i, 0.0).unwrap();
+    }
+    drop(series);
+
+    // a crash after the timestamp but before the value
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .open(dir.0.join(TIMESTAMPS_FILE))
+        .unwrap();
+    let mut timestamps = FileMappedVector::<i64>::options()
+        .type_tag(TIMESTAMPS_TAG)
+        .open::<i64>(file)
+        .unwrap();
+    timestamps.push(10).unwrap();
+    drop(timestamps);
+
+    let series = Series::open(&dir.0).unwrap();
+    assert_eq!(series.len(), 10);
+    assert_eq!(series.timestamps().len(), 10);
+}
+
+#[test]
+fn queries_time_ranges() {
+    let dir = TempDir::new("queries_time_ranges");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    for i in 0..100 {
+        series.append(i * 10, i as f64).unwrap();
+    }
+
+    let points = series.range(100..200);
+    assert_eq!(
+        points.timestamps,
+        &[100, 110, 120, 130, 140, 150, 160, 170, 180, 190]
+    );
+    assert_eq!(points.values[0], 10.0);
+    assert_eq!(series.range(105..=200).len(), 10);
+    assert_eq!(series.range(..0).len(), 0);
+    assert_eq!(series.range(985..).len(), 1);
+
+    assert_eq!(series.first_after(100), Some((110, 11.0)));
+    assert_eq!(series.first_after(105), Some((110, 11.0)));
+    assert_eq!(series.first_after(990), None);
+    assert_eq!(series.last_before(100), Some((90, 9.0)));
+    assert_eq!(series.last_before(0), None);
+}
+
+#[test]
+fn handles_late_points() {
+    let dir = TempDir::new("handles_late_points");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    for ts in [10, 20, 30] {
+        series.append(ts, ts as f64).unwrap();
+    }
+    assert!(matches!(
+        series.append(15, 0.0),
+        Err(FmvError::OutOfOrder { last: 30, ts: 15 })
+    ));
+
+    series.set_out_of_order_policy(OutOfOrderPolicy::Drop);
+    series.append(15, 0.0).unwrap();
+    assert_eq!(series.len(), 3);
+    assert_eq!(series.dropped(), 1);
+
+    series.set_out_of_order_policy(OutOfOrderPolicy::Stage(3));
+    series.append(25, 25.0).unwrap();
+    series.append(5, 5.0).unwrap();
+    series.append(40, 40.0).unwrap();
+    assert_eq!(series.staged(), 2);
+    assert_eq!(series.len(), 4);
+
+    // the third late point fills the stage and triggers a merge
+    series.append(20, 20.5).unwrap();
+    assert_eq!(series.staged(), 0);
+    assert_eq!(series.timestamps(), &[5, 10, 20, 20, 25, 30, 40]);
+    assert_eq!(series.values(), &[5.0, 10.0, 20.0, 20.5, 25.0, 30.0, 40.0]);
+
+    // staged points are merged on drop
+    series.append(35, 35.0).unwrap();
+    drop(series);
+    let series = Series::open(&dir.0).unwrap();
+    assert_eq!(series.timestamps(), &[5, 10, 20, 20, 25, 30, 35, 40]);
+    assert!(!dir.0.join(MERGE_DIR).exists());
+}
+
+#[test]
+fn bounds_late_window() {
+    let dir = TempDir::new("bounds_late_window");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    series.set_out_of_order_policy(OutOfOrderPolicy::Stage(10));
+    series.set_late_window(Some(100));
+    for ts in (0..=1000).step_by(10) {
+        series.append(ts, ts as f64).unwrap();
+    }
+
+    series.append(900, 900.5).unwrap();
+    assert!(matches!(
+        series.append(899, 0.0),
+        Err(FmvError::OutOfOrder { last: 1000, ts: 899 })
+    ));
+    assert_eq!(series.staged(), 1);
+
+    series.merge().unwrap();
+    assert_eq!(series.len(), 102);
+    assert_eq!(series.get(91), Some((900, 900.5)));
+}
+
+#[test]
+fn finishes_committed_merge() {
+    let dir = TempDir::new("finishes_committed_merge");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    for ts in [10, 20, 30] {
+        series.append(ts, ts as f64).unwrap();
+    }
+    drop(series);
+
+    // a merge of 15 and 25 that was committed, but never applied
+    let mut merge = Series::open(dir.0.join(MERGE_DIR)).unwrap();
+    for ts in [15, 20, 25, 30] {
+        merge.append(ts, ts as f64).unwrap();
+    }
+    drop(merge);
+
+    let series = Series::open(&dir.0).unwrap();
+    assert_eq!(series.timestamps(), &[10, 15, 20, 25, 30]);
+    assert_eq!(series.values(), &[10.0, 15.0, 20.0, 25.0, 30.0]);
+    assert!(!dir.0.join(MERGE_DIR).exists());
+}
+
+#[test]
+fn handles_duplicate_timestamps() {
+    let dir = TempDir::new("handles_duplicate_timestamps");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    series.append(10, 1.0).unwrap();
+    series.append(10, 2.0).unwrap();
+    assert_eq!(series.len(), 2);
+
+    series.set_duplicate_policy(DuplicatePolicy::KeepFirst);
+    series.append(20, 1.0).unwrap();
+    series.append(20, 2.0).unwrap();
+    assert_eq!(series.values(), &[1.0, 2.0, 1.0]);
+
+    series.set_duplicate_policy(DuplicatePolicy::KeepLast);
+    series.append(20, 3.0).unwrap();
+    assert_eq!(series.values(), &[1.0, 2.0, 3.0]);
+    assert_eq!(series.dropped(), 2);
+
+    series.set_duplicate_policy(DuplicatePolicy::Error);
+    assert!(matches!(
+        series.append(20, 4.0),
+        Err(FmvError::DuplicateTimestamp(20))
+    ));
+
+    // late duplicates are resolved when merged
+    series.set_duplicate_policy(DuplicatePolicy::KeepLast);
+    series.set_out_of_order_policy(OutOfOrderPolicy::Stage(2));
+    series.append(30, 1.0).unwrap();
+    series.append(20, 5.0).unwrap();
+    series.append(20, 6.0).unwrap();
+    assert_eq!(series.timestamps(), &[10, 10, 20, 30]);
+    assert_eq!(series.values(), &[1.0, 2.0, 6.0, 1.0]);
+    assert_eq!(series.dropped(), 4);
+
+    series.set_duplicate_policy(DuplicatePolicy::Error);
+    assert!(matches!(
+        series.append(20, 7.0),
+        Err(FmvError::DuplicateTimestamp(20))
+    ));
+    assert_eq!(series.staged(), 0);
+
+    // switching to `Error` with a duplicate already staged fails the merge
+    series.set_duplicate_policy(DuplicatePolicy::Allow);
+    series.set_out_of_order_policy(OutOfOrderPolicy::Stage(10));
+    series.append(20, 8.0).unwrap();
+    series.set_duplicate_policy(DuplicatePolicy::Error);
+    assert!(matches!(
+        series.merge(),
+        Err(FmvError::DuplicateTimestamp(20))
+    ));
+    assert_eq!(series.staged(), 1);
+    assert_eq!(series.len(), 4);
+
+    series.set_duplicate_policy(DuplicatePolicy::KeepFirst);
+    series.merge().unwrap();
+    assert_eq!(series.values(), &[1.0, 2.0, 6.0, 1.0]);
+}
+
+#[test]
+fn aggregates_windows() {
+    let dir = TempDir::new("aggregates_windows");
+
+    let mut series = Series::open(&dir.0).unwrap();
+    // two points every 10 units, skipping 30..40
+    for ts in [0, 5, 10, 15, 20, 25, 40, 45] {
+        series.append(ts, ts as f64).unwrap();
+    }
+
+    let agg = |aggregation| series.aggregate(.., 20, aggregation).values;
+    assert_eq!(
+        series.aggregate(.., 20, Aggregation::Count).timestamps,
+        &[0, 20, 40]
+    );
+    assert_eq!(agg(Aggregation::Count), &[4.0, 2.0, 2.0]);
+    assert_eq!(agg(Aggregation::Min), &[0.0, 20.0, 40.0]);
+    assert_eq!(agg(Aggregation::Max), &[15.0, 25.0, 45.0]);
+    assert_eq!(agg(Aggregation::Sum), &[30.0, 45.0, 85.0]);
+    assert_eq!(agg(Aggregation::Mean), &[7.5, 22.5, 42.5]);
+    assert_eq!(agg(Aggregation::First), &[0.0, 20.0, 40.0]);
+    assert_eq!(agg(Aggregation::Last), &[15.0, 25.0, 45.0]);
+    assert_eq!(agg(Aggregation::StdDev), &[5.5901699437494745, 2.5, 2.5]);
+    assert_eq!(
+        series.aggregate(5..=20, 20, Aggregation::Count).values,
+        &[3.0, 1.0]
+    );
+    assert!(series.aggregate(50.., 20, Aggregation::Sum).is_empty());
+}
+
+#[test]
+fn aggregates_extreme_timestamps() {
+    let points = Points {
+        timestamps: &[i64::MIN, i64::MIN + 1, i64::MAX - 1, i64::MAX],
+        values: &[1.0, 2.0, 3.0, 4.0],
+    };
+
+    // i64::MIN is 1 past a multiple of 3, i64::MAX 1 short of one
+    let agg = aggregate(points, 3, Aggregation::Sum);
+    assert_eq!(agg.timestamps, &[i64::MIN, i64::MAX - 1]);
+    assert_eq!(agg.values, &[3.0, 7.0]);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::os::unix::fs::MetadataExt;
+
+use common::TempFile;
+use tsdb_rs::FileMappedVector;
+
+#[test]
+fn truncates_and_pops() {
+    let file = TempFile::new("truncates_and_pops");
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    vec.extend(0..100);
+    let capacity = vec.capacity();
+
+    vec.truncate(150);
+    assert_eq!(vec.len(), 100);
+    vec.truncate(50);
+    assert_eq!(vec.pop(), Some(49));
+    assert_eq!(vec.capacity(), capacity);
+    drop(vec);
+
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert!(vec.as_slice().iter().copied().eq(0..49));
+    assert_eq!(vec.capacity(), capacity);
+
+    vec.clear();
+    assert_eq!(vec.pop(), None);
+    drop(vec);
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert!(vec.is_empty());
+    assert_eq!(vec.capacity(), capacity);
+}
+
+#[test]
+fn sets_len() {
+    let file = TempFile::new("sets_len");
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    vec.extend(0..10);
+
+    unsafe { vec.set_len(4) };
+    assert_eq!(vec.as_slice(), &[0, 1, 2, 3]);
+    // the elements past the length are still there to bring back
+    unsafe { vec.set_len(6) };
+    drop(vec);
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert_eq!(vec.as_slice(), &[0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn shrinks_file_to_fit() {
+    let file = TempFile::new("shrinks_file_to_fit");
+    let mut vec = FileMappedVector::<u64>::with_capacity(file.open(), 1000).unwrap();
+    vec.extend(0..10);
+    assert_eq!(vec.capacity(), 1000);
+
+    vec.shrink_to_fit().unwrap();
+    assert_eq!(vec.capacity(), 10);
+    assert_eq!(file.0.metadata().unwrap().len(), 4096 + 10 * 8);
+
+    // growing again after shrinking
+    vec.push(10).unwrap();
+    assert_eq!(vec.capacity(), 20);
+    drop(vec);
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert!(vec.as_slice().iter().copied().eq(0..11));
+    assert_eq!(vec.capacity(), 20);
+    assert_eq!(file.0.metadata().unwrap().len(), 4096 + 20 * 8);
+}
+
+#[test]
+fn punches_holes() {
+    let file = TempFile::new("punches_holes");
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    vec.extend(1..=4096);
+    vec.flush().unwrap();
+    let size = file.0.metadata().unwrap().len();
+    let blocks = file.0.metadata().unwrap().blocks();
+
+    // elements 512..1536 fill the second and third pages of data exactly
+    vec.punch_hole(512..1536).unwrap();
+    assert!(vec[512..1536].iter().all(|&value| value == 0));
+    assert_eq!(vec.len(), 4096);
+    drop(vec);
+
+    let metadata = file.0.metadata().unwrap();
+    assert_eq!(metadata.len(), size);
+    assert!(metadata.blocks() < blocks);
+
+    let vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+    assert!(vec[..512].iter().copied().eq(1..=512));
+    assert!(vec[512..1536].iter().all(|&value| value == 0));
+    assert!(vec[1536..].iter().copied().eq(1537..=4096));
+    assert_eq!(vec.capacity(), 4096);
+}
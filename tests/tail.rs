@@ -0,0 +1,76 @@
+mod common;
+
+use std::{io::Write, time::Duration};
+
+use common::TempFile;
+use tsdb_rs::{FileMappedReader, FileMappedVector, VectorOptions, MAGIC};
+
+const N: u64 = 200_000;
+
+/// Writer half of `follows_writer_in_another_process`, run in a child process.
+#[test]
+fn tail_writer_child() {
+    let Some(path) = common::child_path() else {
+        return;
+    };
+
+    let mut vec = FileMappedVector::<u64>::new(common::open(&path)).unwrap();
+    for start in (0..N).step_by(10_000) {
+        vec.extend(start..start + 10_000);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn follows_writer_in_another_process() {
+    let file = TempFile::new("follows_writer_in_another_process");
+
+    // initialize the file first so the reader never sees it half-written
+    drop(FileMappedVector::<u64>::new(file.open()).unwrap());
+    let mut reader = FileMappedReader::<u64>::new(&file.open()).unwrap();
+    assert_eq!(reader.len(), 0);
+
+    let mut child = common::child("tail_writer_child", &file.0).spawn().unwrap();
+
+    // every prefix seen along the way must already be fully written
+    let mut seen = 0;
+    while seen < N as usize {
+        seen = reader
+            .wait_for_len(seen + 1, Some(Duration::from_secs(30)))
+            .unwrap();
+        assert!(reader.as_slice().iter().copied().eq(0..seen as u64));
+    }
+
+    assert!(child.wait().unwrap().success());
+    assert_eq!(reader.refresh().unwrap(), N as usize);
+}
+
+#[test]
+fn follows_past_small_reservation() {
+    let file = TempFile::new("follows_past_small_reservation");
+    let mut vec = FileMappedVector::<u64>::new(file.open()).unwrap();
+
+    let mut reader =
+        FileMappedReader::<u64>::with_options(&file.open(), VectorOptions::new().reservation(0))
+            .unwrap();
+    vec.extend(0..100_000);
+    assert_eq!(reader.refresh().unwrap(), 100_000);
+    assert!(reader.as_slice().iter().copied().eq(0..100_000));
+}
+
+#[test]
+fn follows_version_0_file() {
+    let file = TempFile::new("follows_version_0_file");
+
+    // magic, size, then zeroes up to the first element
+    let mut bytes = vec![0; 4096];
+    bytes[..8].copy_from_slice(&MAGIC);
+    bytes[8..16].copy_from_slice(&2u64.to_le_bytes());
+    for value in [7u64, 8] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    file.open().write_all(&bytes).unwrap();
+
+    let reader = FileMappedReader::<u64>::new(&file.open()).unwrap();
+    assert_eq!(reader.as_slice(), &[7, 8]);
+}